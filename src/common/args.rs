@@ -1,6 +1,17 @@
-use std::{fs::File, io::Read};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::File,
+    hash::{Hash, Hasher},
+    io::Read,
+    path::{Path, PathBuf},
+};
 
+use anyhow::{Context, Error};
+
+use crate::common::catalog::Catalog;
 use crate::common::eng::Engines;
+use crate::common::error::ClientError;
+use crate::common::profile::Profile;
 
 #[derive(Debug)]
 pub struct Args {
@@ -8,34 +19,214 @@ pub struct Args {
     pub connection_string: String,
     pub query_file_name: String,
     pub fetch_num: i32,
+    pub vars: Vec<(String, String)>,
+    pub timeout: Option<u64>,
+    pub idle_timeout: Option<u64>,
+    /// Print plans of the queries at the level instead of running them
+    pub explain: Option<u8>,
+    pub profile: Option<String>,
+    pub profiles_file: Option<String>,
+    /// Socket of the daemon to run the script in, instead of connecting directly
+    pub daemon: Option<String>,
+    /// Schema browser request instead of the query file
+    pub browse: Option<Catalog>,
+    /// Schemas to refresh in the schema cache, empty for all
+    pub dump_schema: Option<Vec<String>>,
+    /// Serve the language server protocol on stdio
+    pub lsp: bool,
+}
+
+/// Comma separated names.
+pub fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn usage(msg: String) -> Error {
+    ClientError::Usage(msg).into()
+}
+
+fn parse_engine(name: &str) -> Result<Engines, Error> {
+    match name.to_lowercase().as_str() {
+        "impala" => Ok(Engines::Impala),
+        "oracle" => Ok(Engines::Oracle),
+        "snowflake" => Ok(Engines::Snowflake),
+        _ => Err(usage(format!("Engine {} is not supported", name))),
+    }
 }
 
 impl Args {
-    pub fn parse() -> Self {
-        if std::env::args().count() < 6 {
-            panic!("Not enougth args!")
+    /// Parses command line, `args[0]` is the program name.
+    pub fn from_args(args: Vec<String>) -> Result<Self, Error> {
+        if args
+            .get(1)
+            .is_some_and(|a| a == "browse" || a == "dump-schema" || a == "lsp")
+        {
+            return Self::from_subcommand_args(args);
+        }
+        if args.len() < 6 {
+            return Err(usage(
+                "Expected <engine> <connection_string> <query_file> <_> <fetch_num> [flags]"
+                    .to_string(),
+            ));
+        }
+        let mut parsed = Args {
+            engine: parse_engine(&args[1])?,
+            connection_string: args[2].clone(),
+            query_file_name: args[3].clone(),
+            fetch_num: args[5]
+                .parse::<i32>()
+                .map_err(|_| usage(format!("Fetch number must be a number, got {}", args[5])))?,
+            vars: Vec::new(),
+            timeout: None,
+            idle_timeout: None,
+            explain: None,
+            profile: None,
+            profiles_file: None,
+            daemon: None,
+            browse: None,
+            dump_schema: None,
+            lsp: false,
+        };
+        parsed.parse_flags(&args[6..])?;
+        Ok(parsed)
+    }
+
+    /// `browse <engine> <connection_string> <command> [arg] [flags]`
+    /// `dump-schema <engine> <connection_string> [schema,...] [flags]`
+    /// `lsp <engine> <connection_string> [flags]`
+    fn from_subcommand_args(args: Vec<String>) -> Result<Self, Error> {
+        if args.len() < 4 {
+            return Err(usage(format!(
+                "Expected {} <engine> <connection_string> ...",
+                args[1]
+            )));
+        }
+        let mut parsed = Args {
+            engine: parse_engine(&args[2])?,
+            connection_string: args[3].clone(),
+            query_file_name: String::new(),
+            fetch_num: -1,
+            vars: Vec::new(),
+            timeout: None,
+            idle_timeout: None,
+            explain: None,
+            profile: None,
+            profiles_file: None,
+            daemon: None,
+            browse: None,
+            dump_schema: None,
+            lsp: false,
+        };
+        let positional = |idx: usize| args.get(idx).filter(|a| !a.starts_with("--"));
+        let flags_idx = if args[1] == "browse" {
+            let cmd =
+                positional(4).ok_or_else(|| usage("browse requires a command".to_string()))?;
+            let arg = positional(5);
+            parsed.browse = Some(
+                Catalog::parse(cmd, arg.map(String::as_str))
+                    .map_err(|e| usage(e.to_string()))?
+                    .ok_or_else(|| usage(format!("Unknown browse command {}", cmd)))?,
+            );
+            if arg.is_some() {
+                6
+            } else {
+                5
+            }
+        } else if args[1] == "lsp" {
+            parsed.lsp = true;
+            4
+        } else {
+            let schemas = positional(4);
+            parsed.dump_schema = Some(schemas.map(|s| split_list(s)).unwrap_or_default());
+            if schemas.is_some() {
+                5
+            } else {
+                4
+            }
+        };
+        parsed.parse_flags(&args[flags_idx..])?;
+        Ok(parsed)
+    }
+
+    /// Optional flags after positional args.
+    fn parse_flags(&mut self, flags: &[String]) -> Result<(), Error> {
+        let mut flags = flags.iter().cloned();
+        while let Some(flag) = flags.next() {
+            let value = flags
+                .next()
+                .ok_or_else(|| usage(format!("{} requires a value", flag)))?;
+            let number = |what: &str| usage(format!("{} requires {}, got {}", flag, what, value));
+            match flag.as_str() {
+                "--var" => {
+                    let (name, value) = value
+                        .split_once('=')
+                        .ok_or_else(|| usage("--var requires name=value".to_string()))?;
+                    self.vars.push((name.trim().to_string(), value.to_string()));
+                }
+                "--timeout" => {
+                    self.timeout = Some(value.parse::<u64>().map_err(|_| number("seconds"))?)
+                }
+                "--idle-timeout" => {
+                    self.idle_timeout = Some(value.parse::<u64>().map_err(|_| number("seconds"))?)
+                }
+                "--explain" => {
+                    self.explain = Some(value.parse::<u8>().map_err(|_| number("a level"))?)
+                }
+                "--profile" => self.profile = Some(value),
+                "--profiles-file" => self.profiles_file = Some(value),
+                "--daemon" => self.daemon = Some(value),
+                _ => return Err(usage(format!("Unknown flag {}", flag))),
+            }
         }
-        Args {
-            engine: match get_nth_arg(1).to_lowercase().as_str() {
-                "impala" => Engines::Impala,
-                "oracle" => Engines::Oracle,
-                "snowflake" => Engines::Snowflake,
-                _ => panic!("Engine is not supported"),
-            },
-            connection_string: get_nth_arg(2),
-            query_file_name: get_nth_arg(3),
-            fetch_num: get_nth_arg(5).parse::<i32>().unwrap(),
+        Ok(())
+    }
+
+    /// Identifies the connection: the profile name, or a hash of the connection string
+    /// without a profile.
+    pub fn connection_key(&self) -> String {
+        match &self.profile {
+            Some(profile) => profile.clone(),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                self.connection_string.hash(&mut hasher);
+                format!("{:?}-{:x}", self.engine, hasher.finish())
+            }
         }
     }
 
-    pub fn get_query(&self) -> String {
-        let mut qfile = File::open(&self.query_file_name).unwrap();
+    /// Profile selected by `--profile`, empty one if not set.
+    pub fn load_profile(&self) -> Result<Profile, Error> {
+        match &self.profile {
+            Some(name) => Profile::load(
+                &self
+                    .profiles_file
+                    .as_ref()
+                    .map(PathBuf::from)
+                    .unwrap_or_else(Profile::default_path),
+                name,
+            )
+            .map_err(|e| usage(format!("{:#}", e))),
+            None => Ok(Profile::default()),
+        }
+    }
+
+    pub fn get_query(&self) -> Result<String, Error> {
         let mut contents = String::new();
-        qfile.read_to_string(&mut contents).unwrap();
-        contents
+        File::open(&self.query_file_name)
+            .and_then(|mut qfile| qfile.read_to_string(&mut contents))
+            .map_err(|e| usage(e.to_string()))
+            .with_context(|| format!("Cannot read query file {}", self.query_file_name))?;
+        Ok(contents)
     }
-}
 
-fn get_nth_arg(n: usize) -> String {
-    std::env::args().nth(n).unwrap()
+    /// Directory of the query file, base for relative paths in the script.
+    pub fn query_dir(&self) -> PathBuf {
+        Path::new(&self.query_file_name)
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default()
+    }
 }
@@ -0,0 +1,57 @@
+use std::fmt;
+
+use anyhow::{anyhow, Error};
+
+/// Schema browser request, `table` may be qualified as `schema.table`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Catalog {
+    Catalogs,
+    Schemas,
+    /// Tables matching the pattern, `%` and `_` are wildcards
+    Tables(Option<String>),
+    Columns(String),
+    PrimaryKeys(String),
+    Indexes(String),
+}
+
+impl Catalog {
+    /// Returns `None` if `cmd` is not a schema browser command.
+    pub fn parse(cmd: &str, arg: Option<&str>) -> Result<Option<Self>, Error> {
+        let arg = arg.map(str::trim).filter(|a| !a.is_empty());
+        let table = || {
+            arg.map(str::to_string)
+                .ok_or_else(|| anyhow!("{} requires a table name", cmd))
+        };
+        Ok(Some(match cmd {
+            "catalogs" => Catalog::Catalogs,
+            "schemas" => Catalog::Schemas,
+            "tables" => Catalog::Tables(arg.map(str::to_string)),
+            "columns" => Catalog::Columns(table()?),
+            "primary-keys" => Catalog::PrimaryKeys(table()?),
+            "indexes" => Catalog::Indexes(table()?),
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// Splits `schema.table` into schema and table.
+pub fn split_table(name: &str) -> (Option<&str>, &str) {
+    match name.rsplit_once('.') {
+        Some((schema, table)) => (Some(schema), table),
+        None => (None, name),
+    }
+}
+
+impl fmt::Display for Catalog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Catalog::Catalogs => write!(f, "Catalogs"),
+            Catalog::Schemas => write!(f, "Schemas"),
+            Catalog::Tables(None) => write!(f, "Tables"),
+            Catalog::Tables(Some(pattern)) => write!(f, "Tables like {}", pattern),
+            Catalog::Columns(table) => write!(f, "Columns of {}", table),
+            Catalog::PrimaryKeys(table) => write!(f, "Primary keys of {}", table),
+            Catalog::Indexes(table) => write!(f, "Indexes of {}", table),
+        }
+    }
+}
@@ -6,6 +6,43 @@ pub enum Engines {
 }
 
 
+/// Values of a result row, `None` for NULL.
+pub type Row = Vec<Option<Value>>;
+
+/// Fetched text of a value, dereferences to the text.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Value {
+    pub text: String,
+    /// Bytes which were not valid UTF-8 were replaced, the value is marked when shown
+    pub lossy: bool,
+}
+
+impl Value {
+    pub fn lossy(text: String) -> Self {
+        Value { text, lossy: true }
+    }
+}
+
+impl std::ops::Deref for Value {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl From<String> for Value {
+    fn from(text: String) -> Self {
+        Value { text, lossy: false }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Self {
+        text.to_string().into()
+    }
+}
+
 #[derive(Debug)]
 pub struct ColDesc {
     pub col_id: usize,
@@ -24,4 +61,12 @@ impl ColDesc {
     pub fn get_print_name(&self) -> String {
         format!("{}({})", self.col_name, self.col_type)
     }
+    /// Values are compared and summed as numbers.
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self.col_type.as_str(),
+            "Numeric" | "Decimal" | "Integer" | "SmallInt" | "Float" | "Real" | "Double"
+                | "BigInt" | "TinyInt"
+        )
+    }
 }
\ No newline at end of file
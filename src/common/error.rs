@@ -0,0 +1,116 @@
+use std::fmt;
+
+use anyhow::Error;
+
+/// Class of the failure, `main` exits with its code.
+#[derive(Debug)]
+pub enum ClientError {
+    Connection(String),
+    Auth(String),
+    Syntax(String),
+    Execution(String),
+    /// Statement was cancelled by the driver or by the watchdog after the timeout in seconds.
+    Timeout(u64),
+    Fetch(String),
+    Decoding(String),
+    /// Malformed control command or daemon request.
+    Protocol(String),
+    /// Wrong command line, profile or query file.
+    Usage(String),
+}
+
+impl ClientError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ClientError::Usage(_) => 2,
+            ClientError::Connection(_) => 3,
+            ClientError::Auth(_) => 4,
+            ClientError::Syntax(_) => 5,
+            ClientError::Execution(_) => 6,
+            ClientError::Timeout(_) => 7,
+            ClientError::Fetch(_) => 8,
+            ClientError::Decoding(_) => 9,
+            ClientError::Protocol(_) => 10,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Connection(msg) => write!(f, "Connection error: {}", msg),
+            ClientError::Auth(msg) => write!(f, "Authentication error: {}", msg),
+            ClientError::Syntax(msg) => write!(f, "Syntax error: {}", msg),
+            ClientError::Execution(msg) => write!(f, "Execution error: {}", msg),
+            ClientError::Timeout(t) => write!(f, "Statement timed out after {}s", t),
+            ClientError::Fetch(msg) => write!(f, "Fetch error: {}", msg),
+            ClientError::Decoding(msg) => write!(f, "Decoding error: {}", msg),
+            ClientError::Protocol(msg) => write!(f, "Protocol error: {}", msg),
+            ClientError::Usage(msg) => write!(f, "Usage error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+/// Code of the first classified error in the chain, `1` if there is none.
+pub fn exit_code(e: &Error) -> i32 {
+    // a class added as context is found only by the error itself
+    e.downcast_ref::<ClientError>()
+        .or_else(|| {
+            e.chain()
+                .find_map(|cause| cause.downcast_ref::<ClientError>())
+        })
+        .map_or(1, ClientError::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    #[test]
+    fn exits_with_code_of_class() {
+        let errors = [
+            (ClientError::Usage("u".to_string()), 2),
+            (ClientError::Connection("c".to_string()), 3),
+            (ClientError::Auth("a".to_string()), 4),
+            (ClientError::Syntax("s".to_string()), 5),
+            (ClientError::Execution("e".to_string()), 6),
+            (ClientError::Timeout(30), 7),
+            (ClientError::Fetch("f".to_string()), 8),
+            (ClientError::Decoding("d".to_string()), 9),
+            (ClientError::Protocol("p".to_string()), 10),
+        ];
+        for (e, code) in errors {
+            assert_eq!(e.exit_code(), code, "{}", e);
+            assert_eq!(exit_code(&e.into()), code);
+        }
+    }
+
+    #[test]
+    fn finds_class_in_wrapped_errors() {
+        let e = Error::from(ClientError::Timeout(30)).context("Statement 2 of 3");
+        assert_eq!(exit_code(&e), 7);
+        assert_eq!(
+            format!("{:#}", e),
+            "Statement 2 of 3: Statement timed out after 30s"
+        );
+
+        let e: Result<(), Error> = Err(ClientError::Syntax("near FORM".to_string()).into());
+        let e = e
+            .context("Init SQL failed: SELECT 1 FORM t")
+            .context("Session initialization failed")
+            .unwrap_err();
+        assert_eq!(exit_code(&e), 5);
+
+        // the outermost class wins
+        let e = Error::from(ClientError::Fetch("f".to_string()))
+            .context(ClientError::Protocol("p".to_string()));
+        assert_eq!(exit_code(&e), 10);
+
+        assert_eq!(exit_code(&anyhow!("Cannot read profiles file")), 1);
+        assert_eq!(exit_code(&anyhow!("plain").context("wrapped")), 1);
+    }
+}
@@ -1,10 +1,17 @@
 
 pub mod args;
+pub mod catalog;
 pub mod eng;
+pub mod error;
+pub mod profile;
+pub mod settings;
 pub mod traits;
 
 pub use self::args::Args;
+pub use self::catalog::Catalog;
 pub use self::eng::Engines;
+pub use self::profile::Profile;
+pub use self::settings::Settings;
 pub use self::traits::ConnectionFn;
 
 
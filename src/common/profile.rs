@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error};
+
+/// Named set of options from the profiles file:
+///
+/// ```ini
+/// [prod_impala]
+/// timeout = 300
+/// init_sql = SET MEM_LIMIT=10g
+/// ```
+///
+/// Keys may repeat, e.g. for lists of statements.
+#[derive(Debug, Default)]
+pub struct Profile {
+    pub name: String,
+    values: Vec<(String, String)>,
+}
+
+impl Profile {
+    pub fn load(path: &Path, name: &str) -> Result<Self, Error> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Cannot read profiles file {}", path.display()))?;
+
+        let mut profile: Option<Profile> = None;
+        let mut section = String::new();
+        for (line_idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(s) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = s.trim().to_string();
+                if section == name {
+                    profile = Some(Profile {
+                        name: section.clone(),
+                        values: Vec::new(),
+                    });
+                }
+                continue;
+            }
+            if section != name {
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| {
+                anyhow!("{}:{}: expected key = value", path.display(), line_idx + 1)
+            })?;
+            if let Some(p) = profile.as_mut() {
+                p.values
+                    .push((key.trim().to_lowercase(), value.trim().to_string()));
+            }
+        }
+
+        profile.ok_or_else(|| anyhow!("Profile {} not found in {}", name, path.display()))
+    }
+
+    pub fn default_path() -> PathBuf {
+        let home = std::env::var("HOME").unwrap_or_default();
+        Path::new(&home).join(".jcsql").join("profiles.ini")
+    }
+
+    /// Last value of the key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// All values of the key in the file order.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.values
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    pub fn get_u64(&self, key: &str) -> Result<Option<u64>, Error> {
+        self.get(key)
+            .map(|v| {
+                v.parse::<u64>()
+                    .map_err(|_| anyhow!("Profile {}: {} must be a number", self.name, key))
+            })
+            .transpose()
+    }
+    pub fn get_bool(&self, key: &str) -> Result<Option<bool>, Error> {
+        self.get(key)
+            .map(|v| match v.to_lowercase().as_str() {
+                "on" | "true" => Ok(true),
+                "off" | "false" => Ok(false),
+                _ => Err(anyhow!("Profile {}: {} must be on or off", self.name, key)),
+            })
+            .transpose()
+    }
+}
@@ -0,0 +1,154 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+
+use crate::common::{Args, Profile};
+use crate::engines::cell::{self, BinaryFormat};
+use crate::engines::decoding::Decoding;
+use crate::guard::CostGuard;
+use crate::printing::ValueDisplay;
+use crate::script::SetOption;
+use crate::temporal::TemporalFormat;
+
+const DEFAULT_IDLE_TIMEOUT: u64 = 30;
+
+/// Client options of the session, changed by `SET` directives.
+#[derive(Debug)]
+pub struct Settings {
+    pub fetch_num: i32,
+    pub timing: bool,
+    /// Statement timeout in seconds.
+    pub timeout: Option<u64>,
+    /// Seconds without commands from the plugin before the session is closed.
+    pub idle_timeout: u64,
+    /// Queries are explained at the level instead of being run.
+    pub explain: Option<u8>,
+    /// Metadata cache written by `dump-schema`.
+    pub schema_cache: PathBuf,
+    /// Impala exec summary is printed after the result.
+    pub summary: bool,
+    /// Debug web UI of the Impala coordinator, source of the summary and profile.
+    pub impala_web_url: Option<String>,
+    /// Limits of the estimated scan of queries.
+    pub cost_guard: Option<CostGuard>,
+    /// Statements which can write are not sent to the server.
+    pub read_only: bool,
+    /// DROP, TRUNCATE, DELETE and UPDATE without WHERE are run after confirmation.
+    pub confirm_destructive: bool,
+    /// Changes are committed by `commit` when off.
+    pub autocommit: bool,
+    /// Statements run after connecting, keys `init_sql` and `init_sql.<engine>`.
+    pub init_sql: Vec<String>,
+    /// Text of the values which are not valid UTF-8.
+    pub decoding: Decoding,
+    /// Binary values as truncated hex or whole in base64.
+    pub binary_format: BinaryFormat,
+    /// Fetched bytes of a value, longer values are truncated.
+    pub lob_limit: usize,
+    /// Bytes of a text value in the buffers of a batch, longer values are read again.
+    pub text_limit: usize,
+    /// Text values longer than `text_limit` are truncated instead of being read again.
+    pub truncate_text: bool,
+    /// Formats and time zone of the date, time and timestamp values.
+    pub temporal: Option<TemporalFormat>,
+    /// Markers of NULL and empty values.
+    pub display: ValueDisplay,
+    /// Column statistics of the fetched rows are printed under the grid.
+    pub stats: bool,
+}
+
+impl Settings {
+    /// Flags take precedence over the profile.
+    pub fn new(args: &Args, profile: &Profile) -> Result<Self, Error> {
+        Ok(Settings {
+            fetch_num: args.fetch_num,
+            timing: true,
+            timeout: match args.timeout {
+                Some(timeout) => Some(timeout),
+                None => profile.get_u64("timeout")?,
+            },
+            idle_timeout: match args.idle_timeout {
+                Some(timeout) => timeout,
+                None => profile
+                    .get_u64("idle_timeout")?
+                    .unwrap_or(DEFAULT_IDLE_TIMEOUT),
+            },
+            explain: args.explain,
+            schema_cache: match profile.get("schema_cache") {
+                Some(path) => PathBuf::from(path),
+                None => {
+                    let home = std::env::var("HOME").unwrap_or_default();
+                    Path::new(&home)
+                        .join(".jcsql")
+                        .join("cache")
+                        .join(format!("{}.json", args.connection_key()))
+                }
+            },
+            summary: profile.get_bool("summary")?.unwrap_or(false),
+            impala_web_url: profile.get("impala_web_url").map(str::to_string),
+            cost_guard: CostGuard::from_profile(profile)?,
+            read_only: profile.get_bool("read_only")?.unwrap_or(false),
+            confirm_destructive: profile.get_bool("confirm_destructive")?.unwrap_or(true),
+            autocommit: profile.get_bool("autocommit")?.unwrap_or(true),
+            init_sql: profile
+                .get_all("init_sql")
+                .into_iter()
+                .chain(profile.get_all(&format!(
+                    "init_sql.{}",
+                    format!("{:?}", args.engine).to_lowercase()
+                )))
+                .map(str::to_string)
+                .collect(),
+            decoding: match profile.get("encoding") {
+                Some(name) => Decoding::parse(name).ok_or_else(|| {
+                    anyhow!(
+                        "Profile {}: encoding must be strict, lossy, latin1, windows-1251, \
+                         windows-1252 or hex",
+                        profile.name
+                    )
+                })?,
+                None => Decoding::default(),
+            },
+            binary_format: match profile.get("binary_format") {
+                Some(name) => BinaryFormat::parse(name).ok_or_else(|| {
+                    anyhow!(
+                        "Profile {}: binary_format must be hex or base64",
+                        profile.name
+                    )
+                })?,
+                None => BinaryFormat::default(),
+            },
+            lob_limit: profile
+                .get_u64("lob_limit")?
+                .map_or(cell::DEFAULT_LOB_LIMIT, |limit| limit as usize),
+            text_limit: profile
+                .get_u64("text_limit")?
+                .map_or(cell::DEFAULT_TEXT_LIMIT, |limit| limit as usize),
+            truncate_text: profile.get_bool("truncate_text")?.unwrap_or(false),
+            temporal: TemporalFormat::from_profile(profile)?,
+            display: {
+                let default = ValueDisplay::default();
+                ValueDisplay {
+                    null: profile
+                        .get("null_display")
+                        .map_or(default.null, str::to_string),
+                    empty: profile
+                        .get("empty_display")
+                        .map_or(default.empty, str::to_string),
+                }
+            },
+            stats: profile.get_bool("stats")?.unwrap_or(false),
+        })
+    }
+
+    pub fn set(&mut self, option: SetOption) {
+        match option {
+            SetOption::Fetch(n) => self.fetch_num = n,
+            SetOption::Timing(on) => self.timing = on,
+            SetOption::Timeout(timeout) => self.timeout = timeout,
+            SetOption::Summary(on) => self.summary = on,
+            SetOption::Autocommit(on) => self.autocommit = on,
+            SetOption::Stats(on) => self.stats = on,
+        }
+    }
+}
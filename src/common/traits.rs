@@ -1,12 +1,206 @@
-use anyhow::Error;
+use std::io::Write;
 
-use super::eng::ColDesc;
+use anyhow::{Context, Error};
+
+use super::catalog::Catalog;
+use super::eng::{ColDesc, Engines, Row};
+use crate::plan::{explain_option, explain_statements, option_value, Plan};
 
 pub trait ConnectionFn {
     type Cursor<'a>
     where
         Self: 'a;
 
-    fn execute(&self, q: &str, fetch_num: i32) -> Result<(Vec<ColDesc>, Option<Self::Cursor<'_>>), Error>;
-    fn fetch(&self, c: &mut Self::Cursor<'_>, fetch_num: i32) -> Result<(Vec<Vec<String>>, bool), Error>;
+    fn execute(
+        &self,
+        q: &str,
+        params: &[String],
+        fetch_num: i32,
+        timeout: Option<u64>,
+    ) -> Result<(Vec<ColDesc>, Option<Self::Cursor<'_>>), Error>;
+    /// Schema browser, the result is fetched as of a regular query.
+    fn browse(
+        &self,
+        what: &Catalog,
+        fetch_num: i32,
+    ) -> Result<(Vec<ColDesc>, Option<Self::Cursor<'_>>), Error>;
+    /// Next rows of the result, `None` for NULL, and whether all rows are fetched.
+    fn fetch(&self, c: &mut Self::Cursor<'_>, fetch_num: i32) -> Result<(Vec<Row>, bool), Error>;
+    fn engine(&self) -> Engines;
+    /// Manual commit mode when `false`, switching back commits the open transaction.
+    fn set_autocommit(&self, on: bool) -> Result<(), Error>;
+    fn commit(&self) -> Result<(), Error>;
+    fn rollback(&self) -> Result<(), Error>;
+    /// Runs the query again and writes the whole value of the cell to `out`, row and column
+    /// are 1-based. Returns the number of written bytes.
+    fn dump_cell(
+        &self,
+        q: &str,
+        params: &[String],
+        row: usize,
+        col: u16,
+        out: &mut dyn Write,
+    ) -> Result<u64, Error>;
+
+    /// Runs the session initialization statements, their results are discarded.
+    fn init(&self, statements: &[String]) -> Result<(), Error> {
+        for statement in statements {
+            self.execute(statement, &[], 1, None)
+                .with_context(|| format!("Init SQL failed: {}", statement))?;
+        }
+        Ok(())
+    }
+
+    /// Plan of the query, `level` is the detail level where the engine supports it.
+    /// The query option of the level is set back to its previous value, also on failure.
+    fn explain(&self, q: &str, params: &[String], level: Option<u8>) -> Result<Plan, Error> {
+        let (setup, plan_query) = explain_statements(self.engine(), q, level);
+        let previous = match explain_option(self.engine(), level) {
+            Some((name, default)) => {
+                let value = match self.execute("SET", &[], -1, None)? {
+                    (_, Some(mut cursor)) => option_value(&self.fetch(&mut cursor, -1)?.0, name),
+                    (_, None) => None,
+                };
+                Some((name, value.unwrap_or_else(|| default.to_string())))
+            }
+            None => None,
+        };
+        let run = || -> Result<Vec<Row>, Error> {
+            let mut rows = Vec::new();
+            for statement in setup.iter().chain(std::iter::once(&plan_query)) {
+                // binds belong to the statement with the query
+                let params = if statement.contains(q) { params } else { &[] };
+                if let (_, Some(mut cursor)) = self.execute(statement, params, -1, None)? {
+                    rows = self.fetch(&mut cursor, -1)?.0;
+                }
+            }
+            Ok(rows)
+        };
+        let rows = run();
+        if let Some((name, value)) = previous {
+            let restored = self
+                .execute(&format!("SET {}={}", name, value), &[], 1, None)
+                .with_context(|| format!("Cannot set {} back to {}", name, value));
+            // the failure of the plan is reported first
+            if rows.is_ok() {
+                restored?;
+            }
+        }
+        Plan::parse(self.engine(), &rows?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// Impala session with `EXPLAIN_LEVEL=2`, statements of `missing` fail.
+    #[derive(Default)]
+    struct MockClient {
+        executed: RefCell<Vec<String>>,
+    }
+
+    impl ConnectionFn for MockClient {
+        type Cursor<'a> = Vec<Row>;
+
+        fn execute(
+            &self,
+            q: &str,
+            _params: &[String],
+            _fetch_num: i32,
+            _timeout: Option<u64>,
+        ) -> Result<(Vec<ColDesc>, Option<Vec<Row>>), Error> {
+            self.executed.borrow_mut().push(q.to_string());
+            if q.contains("missing") {
+                return Err(anyhow!("Could not resolve table reference: 'missing'"));
+            }
+            let rows: &[&[&str]] = match q {
+                "SET" => &[
+                    &["MEM_LIMIT", "0", "REGULAR"],
+                    &["explain_level", "2", "REGULAR"],
+                ],
+                _ if q.starts_with("EXPLAIN") => &[&["PLAN-ROOT SINK"]],
+                _ => return Ok((Vec::new(), None)),
+            };
+            let rows = rows
+                .iter()
+                .map(|row| row.iter().map(|v| Some((*v).into())).collect())
+                .collect();
+            Ok((Vec::new(), Some(rows)))
+        }
+
+        fn browse(
+            &self,
+            _what: &Catalog,
+            _fetch_num: i32,
+        ) -> Result<(Vec<ColDesc>, Option<Vec<Row>>), Error> {
+            Ok((Vec::new(), None))
+        }
+
+        fn fetch(&self, c: &mut Vec<Row>, _fetch_num: i32) -> Result<(Vec<Row>, bool), Error> {
+            Ok((std::mem::take(c), true))
+        }
+
+        fn engine(&self) -> Engines {
+            Engines::Impala
+        }
+
+        fn set_autocommit(&self, _on: bool) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn commit(&self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn rollback(&self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn dump_cell(
+            &self,
+            _q: &str,
+            _params: &[String],
+            _row: usize,
+            _col: u16,
+            _out: &mut dyn Write,
+        ) -> Result<u64, Error> {
+            Err(anyhow!("not supported"))
+        }
+    }
+
+    #[test]
+    fn restores_explain_level() {
+        let client = MockClient::default();
+        client.explain("SELECT 1", &[], Some(3)).unwrap();
+        assert_eq!(
+            *client.executed.borrow(),
+            [
+                "SET",
+                "SET EXPLAIN_LEVEL=3",
+                "EXPLAIN SELECT 1",
+                "SET EXPLAIN_LEVEL=2"
+            ]
+        );
+
+        // also when the plan fails, with its error
+        let client = MockClient::default();
+        let e = client
+            .explain("SELECT * FROM missing", &[], Some(3))
+            .unwrap_err();
+        assert!(e.to_string().contains("missing"), "{}", e);
+        assert_eq!(
+            client.executed.borrow().last().unwrap(),
+            "SET EXPLAIN_LEVEL=2"
+        );
+
+        // the level is left alone without one
+        let client = MockClient::default();
+        client.explain("SELECT 1", &[], None).unwrap();
+        assert_eq!(*client.executed.borrow(), ["EXPLAIN SELECT 1"]);
+    }
 }
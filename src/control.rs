@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::{anyhow, Error};
+use chrono::Duration;
+
+pub enum Wait {
+    Input,
+    Timeout,
+    /// stdin is closed, no more commands
+    Closed,
+}
+
+/// Question to the plugin, answered with a line on the control channel.
+pub enum Question<'a> {
+    /// Value of the substitution variable or bind parameter.
+    Value(&'a str),
+    /// Run the statement anyway, the answer is `yes` or `no`.
+    Confirm(&'a str),
+}
+
+impl Question<'_> {
+    pub fn is_yes(answer: &str) -> bool {
+        matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+    }
+}
+
+impl fmt::Display for Question<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Question::Value(name) => write!(f, "Enter value for {}:", name),
+            Question::Confirm(text) => write!(f, "Confirm: {} [yes/no]:", text),
+        }
+    }
+}
+
+/// Control channel with the plugin: commands are read line by line from stdin
+/// in a separate thread and processed by the main thread.
+pub struct Control {
+    input_deque: Arc<Mutex<VecDeque<String>>>,
+    receiver: Receiver<()>,
+}
+
+impl Control {
+    pub fn spawn() -> Self {
+        let input_deque: Arc<Mutex<VecDeque<String>>> = Arc::new(Mutex::new(VecDeque::new()));
+        // Clone the Deque for the separate thread to use
+        let deque_clone = Arc::clone(&input_deque);
+        // Create a channel to communicate between main thread and separate thread
+        let (sender, receiver) = mpsc::channel();
+        // Spawn a separate thread to listen for user input
+        thread::spawn(move || {
+            loop {
+                let mut input = String::new();
+                match std::io::stdin().read_line(&mut input) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        deque_clone
+                            .lock()
+                            .unwrap()
+                            .push_back(input.trim().to_string());
+                        if sender.send(()).is_err() {
+                            break;
+                        }; // Notify the main thread
+                    }
+                    Err(error) => println!("Error: {}", error),
+                }
+            }
+        });
+
+        Control {
+            input_deque,
+            receiver,
+        }
+    }
+
+    /// Waits for notification from the input thread.
+    pub fn wait(&self, timeout: Duration) -> Wait {
+        match self
+            .receiver
+            .recv_timeout(timeout.to_std().unwrap_or_default())
+        {
+            Ok(_) => Wait::Input,
+            Err(RecvTimeoutError::Timeout) => Wait::Timeout,
+            Err(RecvTimeoutError::Disconnected) => Wait::Closed,
+        }
+    }
+
+    pub fn pop(&self) -> Option<String> {
+        self.input_deque.lock().unwrap().pop_front()
+    }
+
+    /// Asks the plugin for a value and blocks until the answer line is received.
+    pub fn prompt(&self, msg: &str) -> Result<String, Error> {
+        println!("{}", msg);
+        std::io::stdout().flush()?;
+        loop {
+            if let Some(input) = self.pop() {
+                return Ok(input);
+            }
+            self.receiver
+                .recv()
+                .map_err(|_| anyhow!("No answer for: {}", msg))?;
+        }
+    }
+}
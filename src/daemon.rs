@@ -0,0 +1,598 @@
+//! Background daemon keeping connections alive between runs.
+//!
+//! Line protocol over a Unix domain socket, fields are separated by `==`:
+//!  - `run==<args>` - run the script, args are the command line args separated by `\t`
+//!  - `command==<session>==<command>` - control command of the session, e.g. `load==N`
+//!  - `cancel==<session>` - cancel the running statement
+//!  - `close==<session>` - close the connection
+//!  - `sessions` - list open sessions
+//!  - `ping`
+//!
+//! Output of the command is sent back, followed by `<<ok>>` or `<<failed>>` line.
+//! Questions of the running script are sent as `<<question>><question>` lines, the client
+//! answers each with a line.
+
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Error};
+
+use crate::common::error::ClientError;
+use chrono::Local;
+
+use crate::common::{Args, ConnectionFn, Engines, Settings};
+use crate::control::{Control, Question, Wait};
+use crate::engines::impala::Impala;
+use crate::engines::odbc::Canceller;
+use crate::printing;
+use crate::script::Vars;
+use crate::session::Session;
+
+const RESPONSE_OK: &str = "<<ok>>";
+const RESPONSE_FAILED: &str = "<<failed>>";
+const RESPONSE_QUESTION: &str = "<<question>>";
+const DEFAULT_IDLE_TIMEOUT: u64 = 3600;
+
+enum Request {
+    Run {
+        args: Box<Args>,
+        output: UnixStream,
+        done: Sender<bool>,
+    },
+    Command {
+        input: String,
+        output: UnixStream,
+        done: Sender<bool>,
+    },
+}
+
+struct SessionHandle {
+    sender: Sender<Request>,
+    canceller: Canceller,
+    last_used: Instant,
+}
+
+type Sessions = Arc<Mutex<HashMap<String, SessionHandle>>>;
+
+pub fn default_socket() -> PathBuf {
+    let home = std::env::var("HOME").unwrap_or_default();
+    Path::new(&home).join(".jcsql").join("daemon.sock")
+}
+
+/// `daemon [socket] [--idle-timeout N]`
+pub fn serve_from_args(args: &[String]) -> Result<(), Error> {
+    let mut socket = default_socket();
+    let mut idle_timeout = DEFAULT_IDLE_TIMEOUT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--idle-timeout" {
+            idle_timeout = args
+                .next()
+                .and_then(|v| v.parse::<u64>().ok())
+                .ok_or_else(|| anyhow!("--idle-timeout requires seconds"))?;
+        } else {
+            socket = PathBuf::from(arg);
+        }
+    }
+    serve(&socket, idle_timeout)
+}
+
+pub fn serve(socket: &Path, idle_timeout: u64) -> Result<(), Error> {
+    if UnixStream::connect(socket).is_ok() {
+        return Err(anyhow!("Daemon is already running on {}", socket.display()));
+    }
+    // stale socket of a killed daemon
+    let _ = fs::remove_file(socket);
+    if let Some(dir) = socket.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("Cannot listen on {}", socket.display()))?;
+    println!("Listening on {}", socket.display());
+
+    let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+    let sessions_clone = Arc::clone(&sessions);
+    thread::spawn(move || evict_idle(sessions_clone, Duration::from_secs(idle_timeout)));
+
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let sessions = Arc::clone(&sessions);
+        thread::spawn(move || {
+            if let Err(e) = handle_client(stream, sessions) {
+                eprintln!("Client error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Closes connections which were not used for the idle timeout.
+fn evict_idle(sessions: Sessions, idle_timeout: Duration) {
+    loop {
+        thread::sleep(Duration::from_secs(10).min(idle_timeout));
+        // dropping the handle stops the session thread and closes the connection
+        sessions
+            .lock()
+            .unwrap()
+            .retain(|_, s| s.last_used.elapsed() < idle_timeout);
+    }
+}
+
+fn handle_client(stream: UnixStream, sessions: Sessions) -> Result<(), Error> {
+    let reader = BufReader::new(stream.try_clone()?);
+    let mut output = stream.try_clone()?;
+
+    for line in reader.lines() {
+        let line = line?;
+        // args of run may contain `==` themselves
+        let fields = if line.starts_with("run==") { 2 } else { 3 };
+        let parts: Vec<&str> = line.trim().splitn(fields, "==").collect();
+        let ok = match parts.as_slice() {
+            ["run", args] => {
+                let session = Args::from_args(
+                    std::iter::once("rust_jcsql_sub")
+                        .chain(args.split('\t'))
+                        .map(|a| a.to_string())
+                        .collect(),
+                )
+                .and_then(|args| {
+                    let key = args.connection_key();
+                    let sender = open_session(&sessions, &key, &args)?;
+                    Ok((args, key, sender))
+                });
+                match session {
+                    Ok((args, key, sender)) => {
+                        let (done, result) = mpsc::channel();
+                        send_request(
+                            &sessions,
+                            &key,
+                            sender,
+                            Request::Run {
+                                args: Box::new(args),
+                                output: stream.try_clone()?,
+                                done,
+                            },
+                            result,
+                            &mut output,
+                        )?
+                    }
+                    Err(e) => {
+                        writeln!(output, "{:#}", e)?;
+                        false
+                    }
+                }
+            }
+            ["command", key, input] => {
+                let input = input.to_string();
+                match touch_session(&sessions, key) {
+                    Some(sender) => {
+                        let (done, result) = mpsc::channel();
+                        send_request(
+                            &sessions,
+                            key,
+                            sender,
+                            Request::Command {
+                                input,
+                                output: stream.try_clone()?,
+                                done,
+                            },
+                            result,
+                            &mut output,
+                        )?
+                    }
+                    None => {
+                        writeln!(output, "Session {} not found", key)?;
+                        false
+                    }
+                }
+            }
+            ["cancel", key] => {
+                let canceller = sessions
+                    .lock()
+                    .unwrap()
+                    .get(*key)
+                    .map(|s| s.canceller.clone());
+                match canceller {
+                    Some(canceller) => {
+                        if !canceller.cancel() {
+                            writeln!(output, "Nothing is running")?;
+                        }
+                        true
+                    }
+                    None => {
+                        writeln!(output, "Session {} not found", key)?;
+                        false
+                    }
+                }
+            }
+            ["close", key] => sessions.lock().unwrap().remove(*key).is_some(),
+            ["sessions"] => {
+                for (key, s) in sessions.lock().unwrap().iter() {
+                    writeln!(output, "{} idle {} s", key, s.last_used.elapsed().as_secs())?;
+                }
+                true
+            }
+            ["ping"] => {
+                writeln!(output, "pong")?;
+                true
+            }
+            _ => {
+                writeln!(output, "Unknown command: {}", line)?;
+                false
+            }
+        };
+        writeln!(output, "{}", if ok { RESPONSE_OK } else { RESPONSE_FAILED })?;
+    }
+    Ok(())
+}
+
+fn touch_session(sessions: &Sessions, key: &str) -> Option<Sender<Request>> {
+    let mut sessions = sessions.lock().unwrap();
+    let session = sessions.get_mut(key)?;
+    session.last_used = Instant::now();
+    Some(session.sender.clone())
+}
+
+/// Returns the session for the key, connecting if there is none.
+fn open_session(sessions: &Sessions, key: &str, args: &Args) -> Result<Sender<Request>, Error> {
+    if let Some(sender) = touch_session(sessions, key) {
+        return Ok(sender);
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let (ready, connected) = mpsc::channel();
+    let canceller = Canceller::default();
+    let canceller_clone = canceller.clone();
+    let engine = args.engine;
+    let connection_string = args.connection_string.clone();
+    let settings = Settings::new(args, &args.load_profile()?)?;
+    thread::spawn(move || {
+        session_thread(
+            engine,
+            connection_string,
+            settings,
+            canceller_clone,
+            receiver,
+            ready,
+        )
+    });
+    // the thread panics if it cannot connect
+    connected
+        .recv()
+        .map_err(|_| anyhow!("Error creating the connection"))??;
+
+    sessions.lock().unwrap().insert(
+        key.to_string(),
+        SessionHandle {
+            sender: sender.clone(),
+            canceller,
+            last_used: Instant::now(),
+        },
+    );
+    Ok(sender)
+}
+
+fn send_request(
+    sessions: &Sessions,
+    key: &str,
+    sender: Sender<Request>,
+    request: Request,
+    result: Receiver<bool>,
+    output: &mut UnixStream,
+) -> Result<bool, Error> {
+    if sender.send(request).is_err() {
+        sessions.lock().unwrap().remove(key);
+        writeln!(output, "Session {} is closed", key)?;
+        return Ok(false);
+    }
+    match result.recv() {
+        Ok(ok) => Ok(ok),
+        Err(_) => {
+            // session thread has died
+            sessions.lock().unwrap().remove(key);
+            writeln!(output, "Session {} has failed", key)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Connects and runs the init statements, also on reconnect as the session state is lost.
+fn connect(engine: Engines, connection_string: &str, settings: &Settings) -> Result<Impala, Error> {
+    let mut client = match engine {
+        Engines::Impala => Impala::new(connection_string.to_string())?,
+        _ => return Err(ClientError::Usage("Not Implemented".to_string()).into()),
+    };
+    client.configure(settings)?;
+    client
+        .init(&settings.init_sql)
+        .context("Session initialization failed")?;
+    Ok(client)
+}
+
+/// Owns the connection, the cursor borrows it, so both live on this thread.
+fn session_thread(
+    engine: Engines,
+    connection_string: String,
+    settings: Settings,
+    canceller: Canceller,
+    receiver: Receiver<Request>,
+    ready: Sender<Result<(), Error>>,
+) {
+    let mut pending: Option<Request> = None;
+    let mut ready = Some(ready);
+    loop {
+        let mut client = match connect(engine, &connection_string, &settings) {
+            Ok(client) => client,
+            Err(e) => {
+                if let Some(ready) = ready.take() {
+                    let _ = ready.send(Err(e));
+                }
+                return;
+            }
+        };
+        client.canceller = canceller.clone();
+        if let Some(ready) = ready.take() {
+            let _ = ready.send(Ok(()));
+        }
+        let mut cursor = None;
+        let mut session: Option<Session> = None;
+
+        while let Some(request) = pending.take().or_else(|| receiver.recv().ok()) {
+            match request {
+                Request::Run { args, output, done } => {
+                    drop(cursor.take());
+                    if !client.is_alive() {
+                        // health check failed, reconnect and run again
+                        pending = Some(Request::Run { args, output, done });
+                        break;
+                    }
+                    let answers = output.try_clone();
+                    printing::redirect(Some(Box::new(output)));
+                    if let Some(mut old) = session.take() {
+                        if let Err(e) = old.end(&client) {
+                            // the changes may still be committed, the connection is not reused
+                            printing::print_line(&format!("{:#}, reconnecting", e));
+                            printing::redirect(None);
+                            match answers {
+                                Ok(output) => {
+                                    pending = Some(Request::Run { args, output, done });
+                                    break;
+                                }
+                                Err(_) => {
+                                    let _ = done.send(false);
+                                    continue;
+                                }
+                            }
+                        }
+                    }
+                    let res = answers
+                        .map_err(Error::from)
+                        .and_then(|answers| run(&client, &mut cursor, &args, answers));
+                    let res = res.map(|s| {
+                        let (new_session, ok) = s;
+                        session = Some(new_session);
+                        ok
+                    });
+                    let ok = res.unwrap_or_else(|e| {
+                        printing::print_line(&format!("{:#}", e));
+                        false
+                    });
+                    printing::spool(None).ok();
+                    printing::redirect(None);
+                    let _ = done.send(ok);
+                }
+                Request::Command {
+                    input,
+                    output,
+                    done,
+                } => {
+                    printing::redirect(Some(Box::new(output)));
+                    let (cmd, arg) = match input.split_once("==") {
+                        Some((cmd, arg)) => (cmd, Some(arg)),
+                        None => (input.as_str(), None),
+                    };
+                    let res = match session.as_mut() {
+                        Some(session) => session.command(&client, &mut cursor, cmd, arg),
+                        None => Err(anyhow!("No script was run")),
+                    };
+                    let ok = match res {
+                        Ok(Some(ok)) => ok,
+                        Ok(None) => {
+                            printing::print_line(&format!("Unknown command: {}", input));
+                            false
+                        }
+                        Err(e) => {
+                            printing::print_line(&format!("{:#}", e));
+                            false
+                        }
+                    };
+                    printing::redirect(None);
+                    let _ = done.send(ok);
+                }
+            }
+        }
+
+        if pending.is_none() {
+            // all handles are dropped
+            if let Some(mut session) = session.take() {
+                let _ = session.end(&client);
+            }
+            drop(cursor);
+            let _ = client.close();
+            return;
+        }
+    }
+}
+
+fn run<'a>(
+    client: &'a Impala,
+    cursor: &mut Option<<Impala as ConnectionFn>::Cursor<'a>>,
+    args: &Args,
+    mut answers: UnixStream,
+) -> Result<(Session, bool), Error> {
+    let profile = args.load_profile()?;
+    let mut session = Session::new(
+        Settings::new(args, &profile)?,
+        Vars::new(&args.vars),
+        args.query_dir(),
+        format!(
+            "[{}] Connected to {:?} (daemon)",
+            Local::now().format("%Y-%m-%d %H:%M:%S"),
+            client.engine
+        ),
+    );
+    // the thin client asks the plugin
+    let mut prompt = |question: &Question| {
+        // not spooled
+        writeln!(answers, "{}{}", RESPONSE_QUESTION, question)?;
+        read_answer(&mut answers).with_context(|| format!("No answer for: {}", question))
+    };
+    let ok = session.start(client, cursor, args, &mut prompt)?;
+    Ok((session, ok))
+}
+
+/// Line sent by the thin client, read byte by byte as the rest of the stream belongs to
+/// the connection thread.
+fn read_answer(stream: &mut UnixStream) -> Result<String, Error> {
+    let (mut line, mut byte) = (Vec::new(), [0u8]);
+    loop {
+        match stream.read(&mut byte)? {
+            0 => return Err(anyhow!("Client has closed the connection")),
+            _ if byte[0] == b'\n' => return Ok(String::from_utf8_lossy(&line).trim().to_string()),
+            _ => line.push(byte[0]),
+        }
+    }
+}
+
+/// Thin client: runs the script in the daemon and forwards control commands to it.
+pub fn run_remote(args: &Args, socket: &Path) -> Result<(), Error> {
+    let stream = UnixStream::connect(socket)
+        .with_context(|| format!("Cannot connect to daemon on {}", socket.display()))?;
+    let key = args.connection_key();
+
+    // the daemon has another working directory
+    let mut argv: Vec<String> = std::env::args().skip(1).collect();
+    if !args.query_file_name.is_empty() {
+        if let Some(path) = argv.iter_mut().find(|a| **a == args.query_file_name) {
+            *path = absolute(path)?;
+        }
+    }
+    for i in 0..argv.len() - 1 {
+        if argv[i] == "--profiles-file" {
+            argv[i + 1] = absolute(&argv[i + 1])?;
+        }
+    }
+
+    // print responses, report the end marker and questions
+    let (status_sender, status) = mpsc::channel();
+    let (question_sender, questions) = mpsc::channel();
+    let reader = BufReader::new(stream.try_clone()?);
+    thread::spawn(move || {
+        for line in reader.lines().map_while(Result::ok) {
+            match line.as_str() {
+                RESPONSE_OK => status_sender.send(true).ok(),
+                RESPONSE_FAILED => status_sender.send(false).ok(),
+                _ => match line.strip_prefix(RESPONSE_QUESTION) {
+                    Some(question) => question_sender.send(question.to_string()).ok(),
+                    None => {
+                        printing::print_line(&line);
+                        Some(())
+                    }
+                },
+            };
+        }
+    });
+
+    let control = Control::spawn();
+    let mut stream = stream;
+    // commands typed while a request is running, sent after it
+    let mut queued = VecDeque::new();
+    let request = |stream: &mut UnixStream,
+                   queued: &mut VecDeque<String>,
+                   cmd: String|
+     -> Result<bool, Error> {
+        writeln!(stream, "{}", cmd)?;
+        loop {
+            match status.recv_timeout(Duration::from_millis(100)) {
+                Ok(ok) => return Ok(ok),
+                Err(mpsc::RecvTimeoutError::Timeout) => {}
+                Err(mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(anyhow!("Daemon has closed the connection"))
+                }
+            }
+            while let Ok(question) = questions.try_recv() {
+                let answer = control.prompt(&question)?;
+                writeln!(stream, "{}", answer)?;
+            }
+            // cancel is sent while the command is running
+            while let Some(input) = control.pop() {
+                match input.as_str() {
+                    "cancel" => {
+                        let mut cancel = UnixStream::connect(socket)?;
+                        writeln!(cancel, "cancel=={}", key)?;
+                    }
+                    "ping" => println!("pong"),
+                    _ => queued.push_back(input),
+                }
+            }
+        }
+    };
+
+    if !request(
+        &mut stream,
+        &mut queued,
+        format!("run=={}", argv.join("\t")),
+    )? {
+        std::process::exit(1);
+    }
+
+    let idle_timeout =
+        chrono::Duration::seconds(Settings::new(args, &args.load_profile()?)?.idle_timeout as i64);
+    loop {
+        while let Some(input) = queued.pop_front().or_else(|| control.pop()) {
+            if input == "ping" {
+                println!("pong");
+                continue;
+            }
+            if input == "cancel" {
+                printing::print_line("Nothing is running");
+                continue;
+            }
+            // the daemon session reports unknown commands and failures, the client goes on
+            request(
+                &mut stream,
+                &mut queued,
+                format!("command=={}=={}", key, input),
+            )?;
+        }
+        match control.wait(idle_timeout) {
+            Wait::Input => {}
+            Wait::Timeout => {
+                printing::print_line(&format!(
+                    "session_expired: no commands for {} s",
+                    idle_timeout.num_seconds()
+                ));
+                print!("done");
+                return Ok(());
+            }
+            Wait::Closed => {
+                print!("done");
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn absolute(path: &str) -> Result<String, Error> {
+    Ok(fs::canonicalize(path)
+        .with_context(|| format!("Cannot find {}", path))?
+        .to_string_lossy()
+        .to_string())
+}
@@ -0,0 +1,357 @@
+//! Values which can be large: binary and LOB columns are read up to a cap and shown shortened.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use odbc_api::parameter::{VarCell, VarKind};
+use odbc_api::DataType;
+
+/// Leading bytes of the binary values shown as hex.
+const HEX_PREVIEW: usize = 16;
+/// Bytes read by one `SQLGetData` call.
+const CHUNK_SIZE: usize = 8192;
+/// Bytes of a value which are fetched, profile key `lob_limit`.
+pub const DEFAULT_LOB_LIMIT: usize = 1024 * 1024;
+/// Bytes of a text value which fit the buffers of a batch, profile key `text_limit`.
+pub const DEFAULT_TEXT_LIMIT: usize = 4096;
+
+/// Chunk of a value read by `SQLGetData`.
+pub type Chunk<K> = VarCell<[u8; CHUNK_SIZE], K>;
+
+/// How binary values are shown, profile key `binary_format`.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum BinaryFormat {
+    /// Leading bytes as `0x89504E47…` with the size.
+    #[default]
+    Hex,
+    /// The whole fetched value, for exports.
+    Base64,
+}
+
+impl BinaryFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "hex" => Some(BinaryFormat::Hex),
+            "base64" => Some(BinaryFormat::Base64),
+            _ => None,
+        }
+    }
+}
+
+/// How the values of the column are fetched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnKind {
+    Text,
+    /// Bytes are fetched as hex text.
+    Binary,
+    /// CLOB, read in chunks up to the cap.
+    LongText,
+    /// BLOB, read in chunks as hex text up to the cap.
+    LongBinary,
+}
+
+impl ColumnKind {
+    pub fn of(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Binary { .. } | DataType::Varbinary { .. } => ColumnKind::Binary,
+            DataType::LongVarbinary { .. } => ColumnKind::LongBinary,
+            DataType::LongVarchar { .. } => ColumnKind::LongText,
+            _ => ColumnKind::Text,
+        }
+    }
+
+    pub fn is_binary(self) -> bool {
+        matches!(self, ColumnKind::Binary | ColumnKind::LongBinary)
+    }
+
+    pub fn is_long(self) -> bool {
+        matches!(self, ColumnKind::LongText | ColumnKind::LongBinary)
+    }
+
+    /// Fetched bytes of a value, binary values take two hex digits per byte.
+    pub fn cap(self, lob_limit: usize) -> usize {
+        match self {
+            ColumnKind::Text | ColumnKind::LongText => lob_limit,
+            ColumnKind::Binary | ColumnKind::LongBinary => lob_limit * 2,
+        }
+    }
+
+    /// Bytes of a value in the buffers of a batch, `len` is the size of the column, 0 if it
+    /// is not known. Longer values are read again up to the lob limit.
+    pub fn buffer_len(self, len: usize, text_limit: usize) -> usize {
+        let cap = self.cap(text_limit);
+        if len == 0 {
+            cap
+        } else {
+            len.min(cap)
+        }
+    }
+}
+
+/// Fetched part of a value.
+#[derive(Debug)]
+pub struct RawCell {
+    pub bytes: Vec<u8>,
+    /// The value is longer than the fetched bytes.
+    pub truncated: bool,
+    /// Length of the whole value when the driver tells it.
+    pub total: Option<usize>,
+}
+
+impl RawCell {
+    /// Fetched bytes without the incomplete character at the end of the truncated text.
+    pub fn text_bytes(&self) -> &[u8] {
+        match std::str::from_utf8(&self.bytes) {
+            Err(e) if self.truncated && e.error_len().is_none() => &self.bytes[..e.valid_up_to()],
+            _ => &self.bytes,
+        }
+    }
+
+    /// Size of the whole value, `per_byte` fetched bytes make one byte of it.
+    pub fn size(&self, per_byte: usize) -> String {
+        match (self.truncated, self.total) {
+            (true, None) => format!("more than {} bytes", self.bytes.len() / per_byte),
+            (true, Some(total)) => format!("{} bytes", total / per_byte),
+            (false, _) => format!("{} bytes", self.bytes.len() / per_byte),
+        }
+    }
+}
+
+/// Binary value fetched as hex text.
+pub fn render_binary(cell: &RawCell, format: BinaryFormat) -> String {
+    match format {
+        BinaryFormat::Hex => {
+            let preview = &cell.bytes[..cell.bytes.len().min(HEX_PREVIEW * 2)];
+            let more = if preview.len() < cell.bytes.len() || cell.truncated {
+                "…"
+            } else {
+                ""
+            };
+            format!(
+                "0x{}{} ({})",
+                String::from_utf8_lossy(preview).to_uppercase(),
+                more,
+                cell.size(2)
+            )
+        }
+        BinaryFormat::Base64 => {
+            let bytes: Vec<u8> = cell
+                .bytes
+                .chunks_exact(2)
+                .filter_map(|pair| u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok())
+                .collect();
+            let text = STANDARD.encode(bytes);
+            if cell.truncated {
+                format!("{}… (truncated, {})", text, cell.size(2))
+            } else {
+                text
+            }
+        }
+    }
+}
+
+/// Reads a value by repeated `get_data` calls passing the chunks to `sink` until it returns
+/// `false`. `None` for NULL, otherwise whether the value was read to the end and its whole
+/// length when the driver tells it.
+pub fn read_chunks<K: VarKind<Element = u8>, E>(
+    mut get_data: impl FnMut(&mut Chunk<K>) -> Result<(), E>,
+    mut sink: impl FnMut(&[u8]) -> bool,
+) -> Result<Option<(bool, Option<usize>)>, E> {
+    let mut buf = Chunk::<K>::NULL;
+    get_data(&mut buf)?;
+    // the first call tells the length of the whole value
+    let total = buf.indicator().value_len();
+    loop {
+        let Some(chunk) = buf.as_bytes() else {
+            return Ok(None);
+        };
+        let more = sink(chunk);
+        if buf.is_complete() {
+            return Ok(Some((true, total)));
+        }
+        if !more {
+            return Ok(Some((false, total)));
+        }
+        get_data(&mut buf)?;
+    }
+}
+
+/// Text of the value up to `cap` bytes, `None` for NULL.
+pub fn read_capped<E>(
+    get_data: impl FnMut(&mut Chunk<odbc_api::parameter::Text>) -> Result<(), E>,
+    cap: usize,
+) -> Result<Option<RawCell>, E> {
+    let mut bytes = Vec::new();
+    let res = read_chunks(get_data, |chunk| {
+        bytes.extend_from_slice(chunk);
+        bytes.len() < cap
+    })?;
+    Ok(res.map(|(complete, total)| {
+        // the last chunk may end past the cap
+        let truncated = !complete || bytes.len() > cap || total.is_some_and(|t| t > cap);
+        bytes.truncate(cap);
+        RawCell {
+            truncated,
+            bytes,
+            total,
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use odbc_api::buffers::Indicator;
+    use odbc_api::parameter::Text;
+
+    use super::*;
+
+    /// `SQLGetData` of the value: a chunk at a time with the remaining length, or `NoTotal`.
+    fn driver(
+        value: Option<&[u8]>,
+        tells_length: bool,
+    ) -> impl FnMut(&mut Chunk<Text>) -> Result<(), ()> + '_ {
+        let mut pos = 0;
+        move |buf| {
+            let Some(value) = value else {
+                *buf = Chunk::NULL;
+                return Ok(());
+            };
+            let rest = &value[pos..];
+            // the last byte of the chunk is the terminating zero
+            let len = rest.len().min(CHUNK_SIZE - 1);
+            let mut chunk = [0u8; CHUNK_SIZE];
+            chunk[..len].copy_from_slice(&rest[..len]);
+            pos += len;
+            let indicator = match tells_length || len == rest.len() {
+                true => Indicator::Length(rest.len()),
+                false => Indicator::NoTotal,
+            };
+            *buf = Chunk::from_buffer(chunk, indicator);
+            Ok(())
+        }
+    }
+
+    fn cell(hex: &str, truncated: bool, total: Option<usize>) -> RawCell {
+        RawCell {
+            bytes: hex.as_bytes().to_vec(),
+            truncated,
+            total,
+        }
+    }
+
+    #[test]
+    fn reads_up_to_the_cap() {
+        let cap = CHUNK_SIZE + 100;
+        let value = vec![b'a'; cap + 1];
+
+        // exactly at the cap over two chunks
+        let cell = read_capped(driver(Some(&value[..cap]), true), cap)
+            .unwrap()
+            .unwrap();
+        assert_eq!((cell.bytes.len(), cell.truncated), (cap, false));
+        assert_eq!(cell.size(1), format!("{} bytes", cap));
+
+        // one byte over
+        let cell = read_capped(driver(Some(&value), true), cap)
+            .unwrap()
+            .unwrap();
+        assert_eq!((cell.bytes.len(), cell.truncated), (cap, true));
+        assert_eq!(cell.size(1), format!("{} bytes", cap + 1));
+        let cell = read_capped(driver(Some(&value), false), cap)
+            .unwrap()
+            .unwrap();
+        assert_eq!((cell.bytes.len(), cell.truncated), (cap, true));
+        assert_eq!(cell.size(1), format!("more than {} bytes", cap));
+
+        // within the first chunk
+        let cell = read_capped(driver(Some(b"abcdef"), true), 6)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (cell.bytes.as_slice(), cell.truncated),
+            (&b"abcdef"[..], false)
+        );
+        let cell = read_capped(driver(Some(b"abcdefg"), true), 6)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            (cell.bytes.as_slice(), cell.truncated),
+            (&b"abcdef"[..], true)
+        );
+
+        assert!(read_capped(driver(None, true), cap).unwrap().is_none());
+        let cell = read_capped(driver(Some(b""), true), cap).unwrap().unwrap();
+        assert_eq!((cell.bytes.len(), cell.truncated), (0, false));
+    }
+
+    #[test]
+    fn drops_incomplete_char_of_truncated_text() {
+        let text = "ab€".as_bytes();
+        assert_eq!(cell("", true, None).text_bytes(), b"");
+        let truncated = RawCell {
+            bytes: text[..3].to_vec(),
+            truncated: true,
+            total: None,
+        };
+        assert_eq!(truncated.text_bytes(), b"ab");
+        // invalid bytes are left for the decoding
+        let invalid = RawCell {
+            bytes: b"ab\xFF".to_vec(),
+            truncated: true,
+            total: None,
+        };
+        assert_eq!(invalid.text_bytes(), b"ab\xFF");
+    }
+
+    #[test]
+    fn renders_binary() {
+        let hex = BinaryFormat::Hex;
+        assert_eq!(
+            render_binary(&cell("89504e47", false, None), hex),
+            "0x89504E47 (4 bytes)"
+        );
+        assert_eq!(render_binary(&cell("", false, None), hex), "0x (0 bytes)");
+        // the preview is 16 bytes
+        let long = "00".repeat(HEX_PREVIEW) + "ff";
+        assert_eq!(
+            render_binary(&cell(&long, false, None), hex),
+            format!("0x{}… (17 bytes)", "00".repeat(HEX_PREVIEW))
+        );
+        assert_eq!(
+            render_binary(&cell(&long[..HEX_PREVIEW * 2], false, None), hex),
+            format!("0x{} (16 bytes)", "00".repeat(HEX_PREVIEW))
+        );
+        assert_eq!(
+            render_binary(&cell("0102", true, Some(2048)), hex),
+            "0x0102… (1024 bytes)"
+        );
+        assert_eq!(
+            render_binary(&cell("0102", true, None), hex),
+            "0x0102… (more than 2 bytes)"
+        );
+
+        let base64 = BinaryFormat::Base64;
+        assert_eq!(
+            render_binary(&cell("89504e47", false, None), base64),
+            "iVBORw=="
+        );
+        assert_eq!(
+            render_binary(&cell("89504e47", true, Some(20)), base64),
+            "iVBORw==… (truncated, 10 bytes)"
+        );
+    }
+
+    #[test]
+    fn limits_buffers_of_batch() {
+        let text_limit = DEFAULT_TEXT_LIMIT;
+        assert_eq!(ColumnKind::Text.buffer_len(10, text_limit), 10);
+        assert_eq!(ColumnKind::Text.buffer_len(0, text_limit), 4096);
+        assert_eq!(ColumnKind::Text.buffer_len(1 << 30, text_limit), 4096);
+        // two hex digits per byte
+        assert_eq!(ColumnKind::Binary.buffer_len(16, text_limit), 16);
+        assert_eq!(ColumnKind::Binary.buffer_len(0, text_limit), 8192);
+        assert_eq!(
+            ColumnKind::Binary.buffer_len(DEFAULT_LOB_LIMIT * 2, text_limit),
+            8192
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Text of the columns which is not valid UTF-8.
+
+use std::fmt::Write;
+use std::str::Utf8Error;
+
+use crate::common::eng::Value;
+
+/// How values which are not valid UTF-8 are decoded, profile key `encoding`.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Decoding {
+    /// Fails the fetch.
+    Strict,
+    /// Invalid sequences are replaced with U+FFFD.
+    #[default]
+    Lossy,
+    /// The whole value is decoded from the legacy charset.
+    Latin1,
+    Windows1251,
+    Windows1252,
+    /// Invalid bytes are shown as `\xNN`.
+    Hex,
+}
+
+/// Windows-1251 0x80..0xBF, 0xC0..0xFF are U+0410..U+044F.
+const WINDOWS_1251: &str = concat!(
+    "ЂЃ‚ѓ„…†‡€‰Љ‹ЊЌЋЏ",
+    "ђ‘’“”•–—\u{FFFD}™љ›њќћџ",
+    "\u{A0}ЎўЈ¤Ґ¦§Ё©Є«¬\u{AD}®Ї",
+    "°±Ііґµ¶·ё№є»јЅѕї",
+);
+
+/// Windows-1252 0x80..0x9F, the rest is Latin-1.
+const WINDOWS_1252: &str = concat!(
+    "€\u{FFFD}‚ƒ„…†‡ˆ‰Š‹Œ\u{FFFD}Ž\u{FFFD}",
+    "\u{FFFD}‘’“”•–—˜™š›œ\u{FFFD}žŸ",
+);
+
+fn table_char(table: &str, idx: usize) -> char {
+    table.chars().nth(idx).unwrap_or('\u{FFFD}')
+}
+
+impl Decoding {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "strict" | "utf-8" | "utf8" => Some(Decoding::Strict),
+            "lossy" => Some(Decoding::Lossy),
+            "latin1" | "latin-1" | "iso-8859-1" => Some(Decoding::Latin1),
+            "windows-1251" | "cp1251" => Some(Decoding::Windows1251),
+            "windows-1252" | "cp1252" => Some(Decoding::Windows1252),
+            "hex" => Some(Decoding::Hex),
+            _ => None,
+        }
+    }
+
+    /// Text of the value, values which are not valid UTF-8 are lossy unless decoded from
+    /// a legacy charset. Fails only in the strict mode.
+    pub fn decode(self, bytes: &[u8]) -> Result<Value, Utf8Error> {
+        let e = match std::str::from_utf8(bytes) {
+            Ok(text) => return Ok(text.into()),
+            Err(e) => e,
+        };
+        let text: String = match self {
+            Decoding::Strict => return Err(e),
+            Decoding::Lossy => return Ok(Value::lossy(String::from_utf8_lossy(bytes).to_string())),
+            Decoding::Hex => return Ok(Value::lossy(hex_invalid(bytes))),
+            Decoding::Latin1 => bytes.iter().map(|b| *b as char).collect(),
+            Decoding::Windows1251 => bytes
+                .iter()
+                .map(|b| match b {
+                    0x80..=0xBF => table_char(WINDOWS_1251, *b as usize - 0x80),
+                    0xC0..=0xFF => char::from_u32(0x0410 + (*b as u32 - 0xC0)).unwrap_or('?'),
+                    _ => *b as char,
+                })
+                .collect(),
+            Decoding::Windows1252 => bytes
+                .iter()
+                .map(|b| match b {
+                    0x80..=0x9F => table_char(WINDOWS_1252, *b as usize - 0x80),
+                    _ => *b as char,
+                })
+                .collect(),
+        };
+        // bytes not defined in the charset
+        Ok(Value {
+            lossy: text.contains('\u{FFFD}'),
+            text,
+        })
+    }
+}
+
+/// Valid parts as text, invalid bytes as `\xNN`.
+fn hex_invalid(mut bytes: &[u8]) -> String {
+    let mut res = String::new();
+    while !bytes.is_empty() {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                res.push_str(text);
+                break;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                res.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                let invalid = e.error_len().unwrap_or(rest.len());
+                for b in &rest[..invalid] {
+                    let _ = write!(res, "\\x{:02X}", b);
+                }
+                bytes = &rest[invalid..];
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_utf8() {
+        for decoding in [
+            Decoding::Strict,
+            Decoding::Lossy,
+            Decoding::Latin1,
+            Decoding::Hex,
+        ] {
+            let value = decoding.decode("café ∅".as_bytes()).unwrap();
+            assert_eq!(value, Value::from("café ∅"));
+        }
+    }
+
+    #[test]
+    fn decodes_legacy_charsets() {
+        let value = Decoding::Latin1.decode(b"caf\xE9").unwrap();
+        assert_eq!(value, Value::from("café"));
+        let value = Decoding::Windows1251
+            .decode(b"\xCF\xF0\xE8\xE2\xE5\xF2 \xA8")
+            .unwrap();
+        assert_eq!(value, Value::from("Привет Ё"));
+        let value = Decoding::Windows1252.decode(b"\x80 na\xEFve").unwrap();
+        assert_eq!(value, Value::from("€ naïve"));
+        // not defined in the charset
+        let value = Decoding::Windows1251.decode(b"a\x98").unwrap();
+        assert_eq!(value, Value::lossy("a\u{FFFD}".to_string()));
+    }
+
+    #[test]
+    fn marks_invalid_utf8_as_lossy() {
+        let bytes = b"ok \xFF\xFEend";
+        let value = Decoding::Lossy.decode(bytes).unwrap();
+        assert_eq!(value, Value::lossy("ok \u{FFFD}\u{FFFD}end".to_string()));
+        let value = Decoding::Hex.decode(bytes).unwrap();
+        assert_eq!(value, Value::lossy("ok \\xFF\\xFEend".to_string()));
+        // truncated sequence at the end
+        let value = Decoding::Hex.decode("é".as_bytes()[..1].as_ref()).unwrap();
+        assert_eq!(&*value, "\\xC3");
+        assert!(Decoding::Strict.decode(bytes).is_err());
+    }
+}
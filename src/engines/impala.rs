@@ -1,18 +1,31 @@
-use crate::common::eng::ColDesc;
+use crate::common::eng::{ColDesc, Row, Value};
+use crate::common::error::ClientError;
+use crate::common::Catalog;
 use crate::common::ConnectionFn;
 use crate::common::Engines;
+use crate::common::Settings;
 
-use anyhow::{Error, Ok};
+use std::io::Write;
+
+use anyhow::{anyhow, Error, Ok};
 use lazy_static::lazy_static;
-use odbc_api::handles::StatementImpl;
+use odbc_api::handles::{SqlText, Statement, StatementImpl};
+use odbc_api::sys::HStmt;
 
 use odbc_api::{
-    buffers::TextRowSet, Connection, ConnectionOptions, Cursor, CursorImpl, DataType, Environment,
-    ResultSetMetadata,
+    buffers::TextRowSet, parameter, BlockCursor, ConnectionOptions, Cursor, CursorImpl, DataType,
+    Environment, IntoParameter, ResultSetMetadata,
+};
+
+use super::cell::{self, BinaryFormat, ColumnKind, RawCell};
+use super::decoding::Decoding;
+use super::odbc::{
+    classify, exec_catalog, get_text_at, map_error, set_query_timeout, Canceller, OdbcConnection,
+    Watchdog,
 };
+use super::oracle;
 
 const MAX_BATCH_SIZE: usize = 5000;
-const MAX_STR_LIMIT: Option<usize> = None;
 
 lazy_static! {
     pub static ref ODBC_ENV: Environment = Environment::new().unwrap();
@@ -21,120 +34,432 @@ lazy_static! {
 pub struct Impala {
     pub engine: Engines,
     pub connection_string: String,
-    connection: Connection<'static>,
+    connection: OdbcConnection,
+    /// Shared with other threads to cancel the running statement
+    pub canceller: Canceller,
+    /// Text of the values which are not valid UTF-8
+    pub decoding: Decoding,
+    pub binary_format: BinaryFormat,
+    /// Fetched bytes of a value
+    pub lob_limit: usize,
+    /// Bytes of a text value in the buffers of a batch
+    pub text_limit: usize,
+    /// Text values longer than `text_limit` are not read again
+    pub truncate_text: bool,
     // res_buffer: Vec<Vec<String>>,
 }
 
 impl Impala {
-    pub fn new(connection_string: String) -> Self {
+    pub fn new(connection_string: String) -> Result<Self, Error> {
         let conn = ODBC_ENV
             .connect_with_connection_string(&connection_string, ConnectionOptions::default())
-            .expect("Error creating the connection");
+            .map_err(|e| classify(e, ClientError::Connection))?;
 
-        Impala {
+        Ok(Impala {
             engine: Engines::Impala,
             connection_string,
-            connection: conn,
+            connection: OdbcConnection::new(conn),
+            canceller: Canceller::default(),
+            decoding: Decoding::default(),
+            binary_format: BinaryFormat::default(),
+            lob_limit: cell::DEFAULT_LOB_LIMIT,
+            text_limit: cell::DEFAULT_TEXT_LIMIT,
+            truncate_text: false,
             // res_buffer: Vec::new(),
+        })
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.connection.is_dead().is_ok_and(|dead| !dead)
+    }
+
+    /// Applies the settings of the profile to the client and the connection. With `read_only`
+    /// the driver is asked for a read-only connection, statements are checked by the session too.
+    pub fn configure(&mut self, settings: &Settings) -> Result<(), Error> {
+        self.decoding = settings.decoding;
+        self.binary_format = settings.binary_format;
+        self.lob_limit = settings.lob_limit;
+        self.text_limit = settings.text_limit;
+        self.truncate_text = settings.truncate_text;
+        if settings.read_only {
+            self.connection
+                .set_read_only()
+                .map_err(|e| classify(e, ClientError::Connection))?;
         }
+        Ok(())
+    }
+
+    /// Disconnects reporting errors, dropping the client ignores them.
+    pub fn close(mut self) -> Result<(), Error> {
+        self.connection.disconnect()?;
+        Ok(())
     }
 
     // pub fn get_res_buffer(&self) -> &Vec<Vec<String>> {
     //     &self.res_buffer
     // }
+
+    /// Executes the statement, returns it with whether the watchdog enforces the timeout.
+    fn exec(
+        &self,
+        q: &str,
+        params: &[String],
+        timeout: Option<u64>,
+    ) -> Result<(StatementImpl<'_>, bool), Error> {
+        let mut stmt = self.connection.allocate_statement()?;
+        let hstmt = stmt.as_sys();
+        let use_watchdog = timeout.is_some_and(|t| !set_query_timeout(hstmt, t));
+
+        let params: Vec<_> = params.iter().map(|p| p.as_str().into_parameter()).collect();
+        for (idx, param) in params.iter().enumerate() {
+            unsafe { stmt.bind_input_parameter(idx as u16 + 1, param) }.into_result(&stmt)?;
+        }
+
+        let watchdog = timeout
+            .filter(|_| use_watchdog)
+            .map(|t| Watchdog::start(hstmt, t));
+        self.canceller.set(Some(hstmt));
+        let res =
+            unsafe { stmt.exec_direct(&SqlText::new(q)) }.into_result_with(&stmt, Some(()), None);
+        self.canceller.set(None);
+        let cancelled = watchdog.is_some_and(|w| w.stop());
+        res.map_err(|e| map_error(e, timeout, cancelled, ClientError::Execution))?;
+        Ok((stmt, use_watchdog))
+    }
+
+    /// Reads again the text and binary values of the fetched batch which did not fit the
+    /// buffers. They stay truncated if the driver can not read the values of the batch with
+    /// `SQLGetData`.
+    fn read_truncated(&self, c: &mut ImpalaCursor<'_>, rows: &mut [Vec<Option<RawCell>>]) {
+        for (row_idx, row) in rows.iter_mut().enumerate() {
+            for (col_idx, value) in row.iter_mut().enumerate() {
+                let kind = c.kinds[col_idx];
+                if !c.get_data_supported || kind.is_long() {
+                    continue;
+                }
+                let Some(value) = value.as_mut().filter(|v| v.truncated) else {
+                    continue;
+                };
+                let cap = kind.cap(self.lob_limit);
+                match get_text_at(c.hstmt, row_idx + 1, col_idx as u16 + 1, cap) {
+                    Some(full) => *value = full,
+                    None => c.get_data_supported = false,
+                }
+            }
+        }
+    }
+
+    /// Text of the fetched value, `None` for NULL.
+    fn render(
+        &self,
+        col_index: usize,
+        kind: ColumnKind,
+        cell: Option<RawCell>,
+    ) -> Result<Option<Value>, Error> {
+        let Some(cell) = cell else {
+            return Ok(None);
+        };
+        if kind.is_binary() {
+            return Ok(Some(cell::render_binary(&cell, self.binary_format).into()));
+        }
+        let mut value = self.decoding.decode(cell.text_bytes()).map_err(|e| {
+            ClientError::Decoding(format!(
+                "column {}: {}, set encoding in the profile to show such values",
+                col_index + 1,
+                e
+            ))
+        })?;
+        if cell.truncated {
+            value.text = format!("{}… (truncated, {})", value.text, cell.size(1));
+        }
+        Ok(Some(value))
+    }
+}
+
+enum Rows<'a> {
+    /// Values are fetched in batches into the bound buffers
+    Block(Box<BlockCursor<CursorImpl<StatementImpl<'a>>, TextRowSet>>),
+    /// The result has LOB columns, values are read row by row in chunks
+    Stream(CursorImpl<StatementImpl<'a>>),
+}
+
+pub struct ImpalaCursor<'a> {
+    rows: Rows<'a>,
+    kinds: Vec<ColumnKind>,
+    /// Values of the fetched batch can be read with `SQLGetData`
+    get_data_supported: bool,
+    /// Rows fetched by one call in the stream mode
+    batch_size: usize,
+    hstmt: HStmt,
+    timeout: Option<u64>,
+    // driver does not support query timeout
+    use_watchdog: bool,
 }
 
 impl ConnectionFn for Impala {
-    type Cursor<'a> = Box<
-        odbc_api::BlockCursor<
-            CursorImpl<StatementImpl<'a>>,
-            odbc_api::buffers::ColumnarBuffer<odbc_api::buffers::TextColumn<u8>>,
-        >,
-    >;
+    type Cursor<'a> = ImpalaCursor<'a>;
 
     fn execute(
         &self,
         q: &str,
+        params: &[String],
         fetch_num_size: i32,
+        timeout: Option<u64>,
     ) -> Result<(Vec<ColDesc>, Option<Self::Cursor<'_>>), Error> {
-        let mut cursor = self.connection.execute(q, ())?.unwrap();
-
-        let mut columns_desc: Vec<ColDesc> = Vec::new();
-        let cols_num = cursor.num_result_cols().unwrap();
-        for col_idx in 1..=cols_num {
-            let col_idx = col_idx as u16;
-            columns_desc.push(ColDesc::new(
-                usize::from(col_idx - 1),
-                cursor.col_name(col_idx)?,
-                match cursor.col_data_type(col_idx)? {
-                    DataType::Char { .. } => "Char",
-                    DataType::WChar { .. } => "Varchar",
-                    DataType::Numeric { .. } => "Numeric",
-                    DataType::Decimal { .. } => "Decimal",
-                    DataType::Integer => "Integer",
-                    DataType::SmallInt => "SmallInt",
-                    DataType::Float { .. } => "Float",
-                    DataType::Real => "Real",
-                    DataType::Double => "Double",
-                    DataType::Varchar { .. } => "Varchar",
-                    DataType::Date => "Date",
-                    DataType::Time { .. } => "Time",
-                    DataType::Timestamp { .. } => "Timestamp",
-                    DataType::BigInt => "BigInt",
-                    DataType::TinyInt => "TinyInt",
-                    DataType::Bit => "Bit",
-                    DataType::Varbinary { .. } => "Varbinary",
-                    DataType::Binary { .. } => "Binary",
-                    DataType::Other { .. } => "Other",
-                    DataType::WVarchar { .. } => "NVarchar",
-                    DataType::LongVarchar { .. } => "TEXT",
-                    DataType::LongVarbinary { .. } => "BLOB",
-                    DataType::Unknown => "Unknown",
-                }
-                .to_owned(),
-            ))
+        let (stmt, use_watchdog) = self.exec(q, params, timeout)?;
+        bind_cursor(stmt, fetch_num_size, timeout, use_watchdog, self.text_limit)
+    }
+
+    fn browse(
+        &self,
+        what: &Catalog,
+        fetch_num: i32,
+    ) -> Result<(Vec<ColDesc>, Option<Self::Cursor<'_>>), Error> {
+        if let Engines::Oracle = self.engine {
+            // the data dictionary instead of the catalog functions
+            let (query, params) = oracle::dictionary_query(what);
+            return self.execute(&query, &params, fetch_num, None);
         }
+        let stmt = self.connection.allocate_statement()?;
+        exec_catalog(stmt.as_sys(), what).into_result(&stmt)?;
+        bind_cursor(stmt, fetch_num, None, false, self.text_limit)
+    }
 
-        let buffers = Box::new(TextRowSet::for_cursor(
-            {
-                if fetch_num_size == -1 || (fetch_num_size as usize) > MAX_BATCH_SIZE {
-                    MAX_BATCH_SIZE
-                } else {
-                    fetch_num_size as usize
-                }
-            },
-            &mut cursor,
-            MAX_STR_LIMIT,
-        )?);
-        let row_set_cursor = Box::new(cursor.bind_buffer(*buffers)?);
-        Ok((columns_desc, Some(row_set_cursor)))
+    fn engine(&self) -> Engines {
+        self.engine
+    }
+
+    fn set_autocommit(&self, on: bool) -> Result<(), Error> {
+        self.connection.set_autocommit(on)?;
+        Ok(())
+    }
+
+    fn commit(&self) -> Result<(), Error> {
+        self.connection.commit()?;
+        Ok(())
+    }
+
+    fn rollback(&self) -> Result<(), Error> {
+        self.connection.rollback()?;
+        Ok(())
+    }
+
+    fn dump_cell(
+        &self,
+        q: &str,
+        params: &[String],
+        row: usize,
+        col: u16,
+        out: &mut dyn Write,
+    ) -> Result<u64, Error> {
+        let (stmt, _) = self.exec(q, params, None)?;
+        if stmt.num_result_cols().into_result(&stmt)? == 0 {
+            return Err(anyhow!("The statement has no result set"));
+        }
+        // Safe: statement is in cursor state
+        let mut cursor = unsafe { CursorImpl::new(stmt) };
+        let binary = ColumnKind::of(&cursor.col_data_type(col)?).is_binary();
+        for _ in 1..row {
+            if cursor.next_row()?.is_none() {
+                return Err(anyhow!("The result has less than {} rows", row));
+            }
+        }
+        let Some(mut cursor_row) = cursor.next_row()? else {
+            return Err(anyhow!("The result has less than {} rows", row));
+        };
+        let mut written = 0;
+        let mut res = std::io::Result::Ok(());
+        let mut sink = |chunk: &[u8]| {
+            res = out.write_all(chunk);
+            written += chunk.len() as u64;
+            res.is_ok()
+        };
+        // binary values are written as is, not as hex
+        if binary {
+            cell::read_chunks::<parameter::Binary, _>(
+                |buf| cursor_row.get_data(col, buf),
+                &mut sink,
+            )?;
+        } else {
+            cell::read_chunks::<parameter::Text, _>(
+                |buf| cursor_row.get_data(col, buf),
+                &mut sink,
+            )?;
+        }
+        res?;
+        Ok(written)
     }
 
-    fn fetch(&self, c: &mut Self::Cursor<'_>, fetch_num: i32) -> Result<(Vec<Vec<String>>, bool), Error> {
-        let mut res_buffer: Vec<Vec<String>> = Vec::new();
+    fn fetch(&self, c: &mut Self::Cursor<'_>, fetch_num: i32) -> Result<(Vec<Row>, bool), Error> {
+        let mut res_buffer: Vec<Row> = Vec::new();
         // Iterate over batches
         let mut fetched = 0;
         while fetched < fetch_num || fetch_num == -1 {
-            if let Some(batch) = c.fetch()? {
-                // Within a batch, iterate over every row
-                fetched += batch.num_rows() as i32;
-                for row_index in 0..batch.num_rows() {
-                    // Within a row iterate over every column
-                    let record = (0..batch.num_cols()).map(|col_index| {
-                        batch.at(col_index, row_index).unwrap_or("NULL".as_bytes())
-                    });
-
-                    let mut row: Vec<String> = Vec::new();
-                    for i in record.into_iter() {
-                        row.push(String::from_utf8(i.to_vec()).unwrap());
-                    }
-                    res_buffer.push(row);
-                }
-            } else {
+            let watchdog = c
+                .timeout
+                .filter(|_| c.use_watchdog)
+                .map(|t| Watchdog::start(c.hstmt, t));
+            self.canceller.set(Some(c.hstmt));
+            let rows = match &mut c.rows {
+                Rows::Block(cursor) => cursor
+                    .fetch()
+                    .map(|batch| batch.map(block_rows).unwrap_or_default()),
+                Rows::Stream(cursor) => stream_rows(cursor, &c.kinds, c.batch_size, self.lob_limit),
+            };
+            self.canceller.set(None);
+            let cancelled = watchdog.is_some_and(|w| w.stop());
+            let mut rows =
+                rows.map_err(|e| map_error(e, c.timeout, cancelled, ClientError::Fetch))?;
+            if matches!(c.rows, Rows::Block(_)) && !self.truncate_text {
+                self.read_truncated(c, &mut rows);
+            }
+            if rows.is_empty() {
                 break;
             }
+            fetched += rows.len() as i32;
+            for row in rows {
+                res_buffer.push(
+                    row.into_iter()
+                        .zip(&c.kinds)
+                        .enumerate()
+                        .map(|(col_index, (cell, kind))| self.render(col_index, *kind, cell))
+                        .collect::<Result<_, _>>()?,
+                );
+            }
         }
         let fetched_all_rows = fetched == 0 || fetched < fetch_num - 1;
         Ok((res_buffer, fetched_all_rows))
     }
 }
+
+/// Binds buffers to the result set of the executed statement, `None` if there is none.
+fn bind_cursor(
+    stmt: StatementImpl<'_>,
+    fetch_num_size: i32,
+    timeout: Option<u64>,
+    use_watchdog: bool,
+    text_limit: usize,
+) -> Result<(Vec<ColDesc>, Option<ImpalaCursor<'_>>), Error> {
+    let hstmt = stmt.as_sys();
+    if stmt.num_result_cols().into_result(&stmt)? == 0 {
+        return Ok((Vec::new(), None));
+    }
+    // Safe: statement is in cursor state
+    let mut cursor = unsafe { CursorImpl::new(stmt) };
+
+    let mut columns_desc: Vec<ColDesc> = Vec::new();
+    let mut data_types = Vec::new();
+    let cols_num = cursor.num_result_cols()?;
+    for col_idx in 1..=cols_num {
+        let col_idx = col_idx as u16;
+        let data_type = cursor.col_data_type(col_idx)?;
+        columns_desc.push(ColDesc::new(
+            usize::from(col_idx - 1),
+            cursor.col_name(col_idx)?,
+            match data_type {
+                DataType::Char { .. } => "Char",
+                DataType::WChar { .. } => "Varchar",
+                DataType::Numeric { .. } => "Numeric",
+                DataType::Decimal { .. } => "Decimal",
+                DataType::Integer => "Integer",
+                DataType::SmallInt => "SmallInt",
+                DataType::Float { .. } => "Float",
+                DataType::Real => "Real",
+                DataType::Double => "Double",
+                DataType::Varchar { .. } => "Varchar",
+                DataType::Date => "Date",
+                DataType::Time { .. } => "Time",
+                DataType::Timestamp { .. } => "Timestamp",
+                DataType::BigInt => "BigInt",
+                DataType::TinyInt => "TinyInt",
+                DataType::Bit => "Bit",
+                DataType::Varbinary { .. } => "Varbinary",
+                DataType::Binary { .. } => "Binary",
+                DataType::Other { .. } => "Other",
+                DataType::WVarchar { .. } => "NVarchar",
+                DataType::LongVarchar { .. } => "TEXT",
+                DataType::LongVarbinary { .. } => "BLOB",
+                DataType::Unknown => "Unknown",
+            }
+            .to_owned(),
+        ));
+        data_types.push(data_type);
+    }
+
+    let batch_size = if fetch_num_size == -1 || (fetch_num_size as usize) > MAX_BATCH_SIZE {
+        MAX_BATCH_SIZE
+    } else {
+        fetch_num_size as usize
+    };
+    let kinds: Vec<ColumnKind> = data_types.iter().map(ColumnKind::of).collect();
+    let rows = if kinds.iter().any(|k| k.is_long()) {
+        Rows::Stream(cursor)
+    } else {
+        let mut max_str_lens = Vec::new();
+        for (col_idx, (data_type, kind)) in data_types.iter().zip(&kinds).enumerate() {
+            let len = match data_type.utf8_len() {
+                Some(len) => len,
+                None => cursor.col_display_size(col_idx as u16 + 1)? as usize,
+            };
+            max_str_lens.push(kind.buffer_len(len, text_limit));
+        }
+        let buffers = TextRowSet::from_max_str_lens(batch_size, max_str_lens)?;
+        Rows::Block(Box::new(cursor.bind_buffer(buffers)?))
+    };
+    Ok((
+        columns_desc,
+        Some(ImpalaCursor {
+            rows,
+            kinds,
+            get_data_supported: true,
+            batch_size,
+            hstmt,
+            timeout,
+            use_watchdog,
+        }),
+    ))
+}
+
+/// Values of the fetched batch.
+fn block_rows(batch: &TextRowSet) -> Vec<Vec<Option<RawCell>>> {
+    (0..batch.num_rows())
+        .map(|row_index| {
+            (0..batch.num_cols())
+                .map(|col_index| {
+                    let bytes = batch.at(col_index, row_index)?;
+                    let indicator = batch.indicator_at(col_index, row_index);
+                    Some(RawCell {
+                        bytes: bytes.to_vec(),
+                        truncated: indicator.is_truncated(bytes.len()),
+                        total: indicator.value_len(),
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Reads up to `batch_size` rows, values of the LOB columns up to the cap.
+fn stream_rows(
+    cursor: &mut CursorImpl<StatementImpl<'_>>,
+    kinds: &[ColumnKind],
+    batch_size: usize,
+    lob_limit: usize,
+) -> Result<Vec<Vec<Option<RawCell>>>, odbc_api::Error> {
+    let mut rows = Vec::new();
+    while rows.len() < batch_size {
+        let Some(mut row) = cursor.next_row()? else {
+            break;
+        };
+        let mut values = Vec::new();
+        for (col_idx, kind) in kinds.iter().enumerate() {
+            let col = col_idx as u16 + 1;
+            values.push(cell::read_capped(
+                |buf| row.get_data(col, buf),
+                kind.cap(lob_limit),
+            )?);
+        }
+        rows.push(values);
+    }
+    std::result::Result::Ok(rows)
+}
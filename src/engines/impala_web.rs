@@ -0,0 +1,189 @@
+//! Query summary and profile from the debug web UI of the Impala coordinator.
+//!
+//! ODBC does not expose the query id, so the query is tagged with a comment and found
+//! in `/queries` by the tag. Any server answering the same endpoints can stand in for
+//! the coordinator, e.g. a local mock serving saved responses.
+
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, Context, Error};
+use serde_json::Value;
+
+use crate::printing;
+
+/// Columns of the exec summary printed after the result.
+const SUMMARY_COLUMNS: [&str; 5] = ["Operator", "Max Time", "#Rows", "Est. #Rows", "Peak Mem"];
+
+pub struct ImpalaWeb {
+    base_url: String,
+}
+
+/// Unique tag of the query in this client.
+pub fn new_tag() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("jcsql:{}:{:x}", process::id(), nanos)
+}
+
+/// Query with the tag in a trailing comment.
+pub fn tag_query(q: &str, tag: &str) -> String {
+    format!("{}\n/* {} */", q, tag)
+}
+
+impl ImpalaWeb {
+    /// `base_url` of the web UI, e.g. `http://coordinator:25000`.
+    pub fn new(base_url: &str) -> Self {
+        ImpalaWeb {
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn get(&self, path: &str) -> Result<String, Error> {
+        let url = format!("{}{}", self.base_url, path);
+        ureq::get(&url)
+            .call()
+            .with_context(|| format!("Request to {} failed", url))?
+            .into_string()
+            .with_context(|| format!("Cannot read response of {}", url))
+    }
+
+    /// Id of the running or completed query with the tag.
+    pub fn query_id(&self, tag: &str) -> Result<String, Error> {
+        let queries: Value = serde_json::from_str(&self.get("/queries?json")?)?;
+        ["in_flight_queries", "completed_queries"]
+            .iter()
+            .filter_map(|list| queries[list].as_array())
+            .flatten()
+            .find(|q| q["stmt"].as_str().is_some_and(|stmt| stmt.contains(tag)))
+            .and_then(|q| q["query_id"].as_str())
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Query is not found on the coordinator"))
+    }
+
+    /// Exec summary table as printed by impala-shell.
+    pub fn summary(&self, query_id: &str) -> Result<String, Error> {
+        let summary: Value = serde_json::from_str(
+            &self.get(&format!("/query_summary?query_id={}&json", query_id))?,
+        )?;
+        summary["summary"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("Summary of {} is empty", query_id))
+    }
+
+    pub fn profile(&self, query_id: &str) -> Result<String, Error> {
+        self.get(&format!("/query_profile_plain_text?query_id={}", query_id))
+    }
+}
+
+/// Aligned table of time, rows and memory per operator from the exec summary table.
+/// The operator is left aligned, other values are right aligned to the end of their header.
+pub fn summary_lines(summary: &str) -> Vec<String> {
+    let mut lines = summary.lines().filter(|l| !l.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Vec::new();
+    };
+    // header names are separated by two spaces at least
+    let mut headers: Vec<(String, usize, usize)> = Vec::new();
+    let mut start = None;
+    let mut spaces = 0;
+    for (idx, ch) in header
+        .char_indices()
+        .chain(std::iter::once((header.len(), ' ')))
+    {
+        if ch == ' ' {
+            spaces += 1;
+            if spaces == 2 {
+                if let Some(s) = start.take() {
+                    let end = idx - 1;
+                    headers.push((header[s..end].to_string(), s, end));
+                }
+            }
+        } else {
+            spaces = 0;
+            start.get_or_insert(idx);
+        }
+    }
+    if let Some(s) = start {
+        headers.push((header[s..].trim_end().to_string(), s, header.len()));
+    }
+
+    // the operator column is as wide as the longest operator
+    let cell = |line: &str, idx: usize| -> String {
+        let range = match idx {
+            0 => 0..headers.get(1).map_or(line.len(), |h| h.1),
+            1 => headers[1].1..headers[1].2,
+            _ if idx == headers.len() - 1 => headers[idx - 1].2..line.len(),
+            _ => headers[idx - 1].2..headers[idx].2,
+        };
+        line.get(range.start.min(line.len())..range.end.min(line.len()))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let selected: Vec<usize> = SUMMARY_COLUMNS
+        .iter()
+        .filter_map(|name| headers.iter().position(|(h, _, _)| h == name))
+        .collect();
+
+    let mut rows = vec![SUMMARY_COLUMNS
+        .iter()
+        .filter(|name| headers.iter().any(|(h, _, _)| h == *name))
+        .map(|name| name.to_string())
+        .collect::<Vec<String>>()];
+    for line in lines.filter(|l| !l.starts_with("---")) {
+        rows.push(selected.iter().map(|idx| cell(line, *idx)).collect());
+    }
+    printing::align(&rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUMMARY: &str = include_str!("../../tests/fixtures/impala_summary.txt");
+    const SUMMARY_NO_ESTIMATES: &str =
+        include_str!("../../tests/fixtures/impala_summary_no_estimates.txt");
+
+    #[test]
+    fn selects_summary_columns() {
+        assert_eq!(
+            summary_lines(SUMMARY),
+            [
+                "Operator             Max Time   #Rows  Est. #Rows  Peak Mem",
+                // lines end after the last value
+                "F02:ROOT             62.502us                      0",
+                "04:MERGING-EXCHANGE  107.503us  5      5           16.00 KB",
+                "F01:EXCHANGE SENDER  120.004us                     3.86 KB",
+                "02:TOP-N             159.005us  5      5           16.00 KB",
+                "03:AGGREGATE         1.301ms    5      5           10.14 MB",
+                "01:AGGREGATE         2.801ms    15     5           10.19 MB",
+                "00:SCAN HDFS         52.203ms   1.20M  1.20M       8.20 MB",
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_missing_columns() {
+        assert_eq!(
+            summary_lines(SUMMARY_NO_ESTIMATES),
+            [
+                "Operator      Max Time  #Rows  Peak Mem",
+                "01:EXCHANGE   20.001us  3      16.00 KB",
+                "00:SCAN HDFS  9.000ms   3      1.02 MB",
+            ]
+        );
+        // a line cut in the middle of the values
+        let cut: String = SUMMARY_NO_ESTIMATES
+            .lines()
+            .take(3)
+            .collect::<Vec<_>>()
+            .join("\n");
+        let cut = format!("{}\n00:SCAN HDFS       2   8.5", cut);
+        assert_eq!(summary_lines(&cut)[2..], ["00:SCAN HDFS"]);
+        assert!(summary_lines("").is_empty());
+    }
+}
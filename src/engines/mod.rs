@@ -1,3 +1,7 @@
+pub mod cell;
+pub mod decoding;
 pub mod impala;
+pub mod impala_web;
+pub mod odbc;
 pub mod oracle;
 pub mod snowflake;
@@ -0,0 +1,377 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use anyhow::Error;
+use odbc_api::handles::{self, CData, CDataMut, SqlResult, State, StatementImpl};
+use odbc_api::sys::{
+    self, ConnectionAttribute, HStmt, Pointer, SmallInt, SqlReturn, StatementAttribute, ULen,
+    USmallInt,
+};
+
+use crate::common::catalog::{split_table, Catalog};
+use crate::common::error::ClientError;
+
+use super::cell::{self, RawCell};
+
+const TIMEOUT_EXPIRED: State = State(*b"HYT00");
+const INVALID_TRANSACTION_STATE: State = State(*b"25000");
+/// Optional feature, attribute or its value which the driver does not support.
+const NOT_SUPPORTED: [State; 3] = [State(*b"HYC00"), State(*b"HY092"), State(*b"HY024")];
+const SQL_MODE_READ_ONLY: usize = 1;
+const SQL_INDEX_ALL: USmallInt = 1;
+const SQL_QUICK: USmallInt = 0;
+const SQL_POSITION: USmallInt = 0;
+const SQL_LOCK_NO_CHANGE: USmallInt = 0;
+
+// Not declared by odbc-sys, the library is linked by it.
+extern "system" {
+    fn SQLPrimaryKeys(
+        statement_handle: HStmt,
+        catalog_name: *const u8,
+        catalog_name_length: SmallInt,
+        schema_name: *const u8,
+        schema_name_length: SmallInt,
+        table_name: *const u8,
+        table_name_length: SmallInt,
+    ) -> SqlReturn;
+    fn SQLStatistics(
+        statement_handle: HStmt,
+        catalog_name: *const u8,
+        catalog_name_length: SmallInt,
+        schema_name: *const u8,
+        schema_name_length: SmallInt,
+        table_name: *const u8,
+        table_name_length: SmallInt,
+        unique: USmallInt,
+        reserved: USmallInt,
+    ) -> SqlReturn;
+    fn SQLSetPos(
+        statement_handle: HStmt,
+        row_number: ULen,
+        operation: USmallInt,
+        lock_type: USmallInt,
+    ) -> SqlReturn;
+}
+
+fn succeeded(ret: SqlReturn) -> bool {
+    ret == SqlReturn::SUCCESS || ret == SqlReturn::SUCCESS_WITH_INFO
+}
+
+/// Reads the text value in the 1-based row of the fetched batch up to `cap` bytes.
+/// `None` if the driver does not support `SQLGetData` for the bound columns of the batch.
+pub fn get_text_at(hstmt: HStmt, row: usize, col: u16, cap: usize) -> Option<RawCell> {
+    if !succeeded(unsafe { SQLSetPos(hstmt, row as ULen, SQL_POSITION, SQL_LOCK_NO_CHANGE) }) {
+        return None;
+    }
+    let value = cell::read_capped(
+        |buf| {
+            let ret = unsafe {
+                sys::SQLGetData(
+                    hstmt,
+                    col,
+                    buf.cdata_type(),
+                    buf.mut_value_ptr(),
+                    buf.buffer_length(),
+                    buf.mut_indicator_ptr(),
+                )
+            };
+            if succeeded(ret) {
+                Ok(())
+            } else {
+                Err(())
+            }
+        },
+        cap,
+    );
+    // the value was truncated, so it is not NULL
+    value.ok().flatten()
+}
+
+/// Sets `SQL_ATTR_QUERY_TIMEOUT`. Returns `false` if the driver does not support it
+/// or changed the value.
+pub fn set_query_timeout(hstmt: HStmt, timeout: u64) -> bool {
+    let ret = unsafe {
+        sys::SQLSetStmtAttr(
+            hstmt,
+            StatementAttribute::QueryTimeout,
+            timeout as usize as Pointer,
+            0,
+        )
+    };
+    ret == SqlReturn::SUCCESS
+}
+
+/// Open connection, unlike [`odbc_api::Connection`] its handle is available for the
+/// attributes odbc-api does not set.
+pub struct OdbcConnection(handles::Connection<'static>);
+
+impl OdbcConnection {
+    pub fn new(connection: odbc_api::Connection<'static>) -> Self {
+        OdbcConnection(connection.into_handle())
+    }
+
+    pub fn allocate_statement(&self) -> Result<StatementImpl<'_>, odbc_api::Error> {
+        self.0.allocate_statement().into_result(&self.0)
+    }
+
+    pub fn is_dead(&self) -> Result<bool, odbc_api::Error> {
+        self.0.is_dead().into_result(&self.0)
+    }
+
+    pub fn set_autocommit(&self, on: bool) -> Result<(), odbc_api::Error> {
+        self.0.set_autocommit(on).into_result(&self.0)
+    }
+
+    pub fn commit(&self) -> Result<(), odbc_api::Error> {
+        self.0.commit().into_result(&self.0)
+    }
+
+    pub fn rollback(&self) -> Result<(), odbc_api::Error> {
+        self.0.rollback().into_result(&self.0)
+    }
+
+    /// Sets `SQL_ATTR_ACCESS_MODE` to read-only, which is only a hint for the driver.
+    /// Drivers which do not support it are ignored.
+    pub fn set_read_only(&self) -> Result<(), odbc_api::Error> {
+        let ret = unsafe {
+            sys::SQLSetConnectAttr(
+                self.0.as_sys(),
+                ConnectionAttribute::AccessMode,
+                SQL_MODE_READ_ONLY as Pointer,
+                0,
+            )
+        };
+        let res = match ret {
+            SqlReturn::SUCCESS => SqlResult::Success(()),
+            SqlReturn::SUCCESS_WITH_INFO => SqlResult::SuccessWithInfo(()),
+            _ => SqlResult::Error {
+                function: "SQLSetConnectAttr",
+            },
+        };
+        match res.into_result(&self.0) {
+            Err(odbc_api::Error::Diagnostics { record, .. })
+                if NOT_SUPPORTED.contains(&record.state) =>
+            {
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// Disconnects reporting errors, dropping the connection only frees the handle.
+    pub fn disconnect(&mut self) -> Result<(), odbc_api::Error> {
+        match self.0.disconnect().into_result(&self.0) {
+            // the open transaction is rolled back as by odbc-api
+            Err(odbc_api::Error::Diagnostics { record, .. })
+                if record.state == INVALID_TRANSACTION_STATE =>
+            {
+                self.rollback()?;
+                self.0.disconnect().into_result(&self.0)
+            }
+            res => res,
+        }
+    }
+}
+
+impl Drop for OdbcConnection {
+    fn drop(&mut self) {
+        // already disconnected by `Impala::close`, or the error cannot be reported
+        let _ = self.disconnect();
+    }
+}
+
+struct StmtHandle(HStmt);
+
+// SQLCancel is the one function which is allowed to be called from another thread
+// while the statement is in use.
+unsafe impl Send for StmtHandle {}
+
+/// Statement running on a connection, which can be cancelled from another thread.
+#[derive(Clone, Default)]
+pub struct Canceller(Arc<Mutex<Option<StmtHandle>>>);
+
+impl Canceller {
+    /// Must be reset to `None` before the statement is freed.
+    pub fn set(&self, hstmt: Option<HStmt>) {
+        *self.0.lock().unwrap() = hstmt.map(StmtHandle);
+    }
+
+    /// Returns `false` if nothing is running.
+    pub fn cancel(&self) -> bool {
+        match self.0.lock().unwrap().as_ref() {
+            Some(handle) => {
+                let _ = unsafe { sys::SQLCancel(handle.0) };
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Cancels the statement from a separate thread if it is not stopped within the timeout.
+pub struct Watchdog {
+    stop: Sender<()>,
+    fired: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Watchdog {
+    pub fn start(hstmt: HStmt, timeout: u64) -> Self {
+        let (stop, receiver) = mpsc::channel();
+        let fired = Arc::new(AtomicBool::new(false));
+        let fired_clone = Arc::clone(&fired);
+        let handle = StmtHandle(hstmt);
+        let thread = thread::spawn(move || {
+            let handle = handle;
+            if let Err(RecvTimeoutError::Timeout) =
+                receiver.recv_timeout(Duration::from_secs(timeout))
+            {
+                fired_clone.store(true, Ordering::SeqCst);
+                let _ = unsafe { sys::SQLCancel(handle.0) };
+            }
+        });
+        Watchdog {
+            stop,
+            fired,
+            thread,
+        }
+    }
+
+    /// Stops the watchdog. Returns `true` if it has cancelled the statement.
+    pub fn stop(self) -> bool {
+        let _ = self.stop.send(());
+        // wait for a running cancel, the statement may be freed right after
+        let _ = self.thread.join();
+        self.fired.load(Ordering::SeqCst)
+    }
+}
+
+/// Class of the driver error by its SQLSTATE, `other` is the class of the failed operation.
+pub fn classify(e: odbc_api::Error, other: fn(String) -> ClientError) -> ClientError {
+    let state_class = match &e {
+        odbc_api::Error::Diagnostics { record, .. } => Some([record.state.0[0], record.state.0[1]]),
+        _ => None,
+    };
+    let msg = e.to_string();
+    match state_class.as_ref() {
+        Some(b"08") if msg.to_lowercase().contains("authentication") => ClientError::Auth(msg),
+        Some(b"08") => ClientError::Connection(msg),
+        Some(b"28") => ClientError::Auth(msg),
+        Some(b"42") | Some(b"37") => ClientError::Syntax(msg),
+        _ => other(msg),
+    }
+}
+
+/// Replaces driver timeout and cancel errors with [`ClientError::Timeout`], other errors
+/// are classified.
+pub fn map_error(
+    e: odbc_api::Error,
+    timeout: Option<u64>,
+    cancelled: bool,
+    other: fn(String) -> ClientError,
+) -> Error {
+    match (timeout, &e) {
+        (Some(t), _) if cancelled => ClientError::Timeout(t).into(),
+        (Some(t), odbc_api::Error::Diagnostics { record, .. })
+            if record.state == TIMEOUT_EXPIRED =>
+        {
+            ClientError::Timeout(t).into()
+        }
+        _ => classify(e, other).into(),
+    }
+}
+
+/// Null pointer means the argument is not used as a filter.
+fn text_arg(text: Option<&str>) -> (*const u8, SmallInt) {
+    match text {
+        Some(text) => (text.as_ptr(), text.len() as SmallInt),
+        None => (std::ptr::null(), 0),
+    }
+}
+
+/// Runs the catalog function for the request, the result set is left on the statement.
+pub fn exec_catalog(hstmt: HStmt, what: &Catalog) -> SqlResult<()> {
+    let (catalog, schema, table) = match what {
+        Catalog::Catalogs => (Some("%"), Some(""), Some("")),
+        Catalog::Schemas => (Some(""), Some("%"), Some("")),
+        Catalog::Tables(pattern) => {
+            let (schema, table) = split_table(pattern.as_deref().unwrap_or("%"));
+            (None, schema, Some(table))
+        }
+        Catalog::Columns(name) | Catalog::PrimaryKeys(name) | Catalog::Indexes(name) => {
+            let (schema, table) = split_table(name);
+            (None, schema, Some(table))
+        }
+    };
+    let (catalog, catalog_len) = text_arg(catalog);
+    let (schema, schema_len) = text_arg(schema);
+    let (table, table_len) = text_arg(table);
+
+    let (function, ret) = unsafe {
+        match what {
+            Catalog::Catalogs | Catalog::Schemas | Catalog::Tables(_) => (
+                "SQLTables",
+                sys::SQLTables(
+                    hstmt,
+                    catalog,
+                    catalog_len,
+                    schema,
+                    schema_len,
+                    table,
+                    table_len,
+                    std::ptr::null(),
+                    0,
+                ),
+            ),
+            Catalog::Columns(_) => (
+                "SQLColumns",
+                sys::SQLColumns(
+                    hstmt,
+                    catalog,
+                    catalog_len,
+                    schema,
+                    schema_len,
+                    table,
+                    table_len,
+                    std::ptr::null(),
+                    0,
+                ),
+            ),
+            Catalog::PrimaryKeys(_) => (
+                "SQLPrimaryKeys",
+                SQLPrimaryKeys(
+                    hstmt,
+                    catalog,
+                    catalog_len,
+                    schema,
+                    schema_len,
+                    table,
+                    table_len,
+                ),
+            ),
+            Catalog::Indexes(_) => (
+                "SQLStatistics",
+                SQLStatistics(
+                    hstmt,
+                    catalog,
+                    catalog_len,
+                    schema,
+                    schema_len,
+                    table,
+                    table_len,
+                    SQL_INDEX_ALL,
+                    SQL_QUICK,
+                ),
+            ),
+        }
+    };
+    match ret {
+        SqlReturn::SUCCESS => SqlResult::Success(()),
+        SqlReturn::SUCCESS_WITH_INFO => SqlResult::SuccessWithInfo(()),
+        SqlReturn::NO_DATA => SqlResult::NoData,
+        _ => SqlResult::Error { function },
+    }
+}
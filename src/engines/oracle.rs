@@ -0,0 +1,83 @@
+use crate::common::catalog::{split_table, Catalog};
+
+/// Dictionary query and its binds for the schema browser request.
+/// Columns are named as in the ODBC catalog functions, names without a schema are looked up
+/// in the schema of the current user.
+pub fn dictionary_query(what: &Catalog) -> (String, Vec<String>) {
+    let owner_and_table = |name: &str| {
+        let (schema, table) = split_table(name);
+        vec![schema.unwrap_or_default().to_string(), table.to_string()]
+    };
+    let (query, params) = match what {
+        Catalog::Catalogs => (
+            "SELECT SYS_CONTEXT('USERENV', 'DB_NAME') AS table_cat FROM dual",
+            Vec::new(),
+        ),
+        Catalog::Schemas => (
+            "SELECT username AS table_schem FROM all_users ORDER BY username",
+            Vec::new(),
+        ),
+        Catalog::Tables(pattern) => (
+            "SELECT owner AS table_schem, object_name AS table_name, object_type AS table_type
+               FROM all_objects
+              WHERE object_type IN ('TABLE', 'VIEW')
+                AND owner = NVL(UPPER(?), USER)
+                AND object_name LIKE UPPER(?)
+              ORDER BY owner, object_name",
+            owner_and_table(pattern.as_deref().unwrap_or("%")),
+        ),
+        Catalog::Columns(table) => (
+            "SELECT owner AS table_schem, table_name, column_name, data_type AS type_name,
+                    data_length, data_precision, data_scale, nullable, data_default
+               FROM all_tab_columns
+              WHERE owner = NVL(UPPER(?), USER)
+                AND table_name LIKE UPPER(?)
+              ORDER BY table_name, column_id",
+            owner_and_table(table),
+        ),
+        Catalog::PrimaryKeys(table) => (
+            "SELECT c.owner AS table_schem, c.table_name, cc.column_name, cc.position AS key_seq,
+                    c.constraint_name AS pk_name
+               FROM all_constraints c
+               JOIN all_cons_columns cc
+                 ON cc.owner = c.owner AND cc.constraint_name = c.constraint_name
+              WHERE c.constraint_type = 'P'
+                AND c.owner = NVL(UPPER(?), USER)
+                AND c.table_name = UPPER(?)
+              ORDER BY cc.position",
+            owner_and_table(table),
+        ),
+        Catalog::Indexes(table) => (
+            "SELECT i.table_owner AS table_schem, i.table_name, i.uniqueness, i.index_name,
+                    ic.column_position AS ordinal_position, ic.column_name
+               FROM all_indexes i
+               JOIN all_ind_columns ic
+                 ON ic.index_owner = i.owner AND ic.index_name = i.index_name
+              WHERE i.table_owner = NVL(UPPER(?), USER)
+                AND i.table_name = UPPER(?)
+              ORDER BY i.index_name, ic.column_position",
+            owner_and_table(table),
+        ),
+    };
+    (query.to_string(), params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_owner_and_table() {
+        let (query, params) = dictionary_query(&Catalog::Columns("sales.orders".to_string()));
+        assert!(query.contains("FROM all_tab_columns"), "{}", query);
+        assert_eq!(params, ["sales", "orders"]);
+
+        // the schema of the current user
+        let (_, params) = dictionary_query(&Catalog::PrimaryKeys("orders".to_string()));
+        assert_eq!(params, ["", "orders"]);
+        let (query, params) = dictionary_query(&Catalog::Tables(None));
+        assert!(query.contains("FROM all_objects"), "{}", query);
+        assert_eq!(params, ["", "%"]);
+        assert!(dictionary_query(&Catalog::Schemas).1.is_empty());
+    }
+}
@@ -0,0 +1,340 @@
+//! Checks of statements before they are sent to the server.
+
+use anyhow::{anyhow, Error};
+
+use crate::common::Profile;
+use crate::plan::{parse_size, ScanEstimate};
+
+/// First keyword of the statement in lowercase, comments and parentheses are skipped.
+pub fn keyword(query: &str) -> String {
+    skip_comments(query)
+        .chars()
+        .take_while(|c| is_word_char(*c))
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn skip_comments(query: &str) -> &str {
+    let mut rest = query;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '(');
+        if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, r)| r);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, r)| r);
+        } else {
+            break;
+        }
+    }
+    rest
+}
+
+/// Statements changing data, schema or privileges.
+const WRITE_KEYWORDS: [&str; 5] = ["insert", "update", "delete", "merge", "upsert"];
+
+/// Whether the statement can change data, schema or privileges.
+#[derive(Debug, PartialEq)]
+pub enum StatementKind {
+    Read,
+    Write,
+}
+
+/// Statements not known to be read only are writes, e.g. PL/SQL blocks and `CALL`.
+pub fn classify(query: &str) -> StatementKind {
+    match keyword(query).as_str() {
+        // `WITH ... INSERT` on Impala, any DML word is enough to be on the safe side
+        "with" => {
+            let words = query
+                .split(|c: char| !is_word_char(c))
+                .map(str::to_lowercase);
+            for word in words {
+                if WRITE_KEYWORDS.contains(&word.as_str()) {
+                    return StatementKind::Write;
+                }
+            }
+            StatementKind::Read
+        }
+        "select" | "show" | "describe" | "desc" | "explain" | "values" | "set" | "use" => {
+            StatementKind::Read
+        }
+        "alter" if second_keyword(query) == "session" => StatementKind::Read,
+        _ => StatementKind::Write,
+    }
+}
+
+fn second_keyword(query: &str) -> String {
+    let rest = skip_comments(query);
+    keyword(rest.trim_start_matches(is_word_char))
+}
+
+/// Words of the statement with their depth in parentheses, string literals and comments
+/// are skipped.
+fn words(query: &str) -> Vec<(usize, String)> {
+    let mut words = Vec::new();
+    let mut depth = 0usize;
+    let mut rest = query;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || ",;".contains(c));
+        if let Some(inner) = rest.strip_prefix('(') {
+            depth += 1;
+            rest = inner;
+        } else if let Some(outer) = rest.strip_prefix(')') {
+            depth = depth.saturating_sub(1);
+            rest = outer;
+        } else if let Some(comment) = rest.strip_prefix("--") {
+            rest = comment.split_once('\n').map_or("", |(_, r)| r);
+        } else if let Some(comment) = rest.strip_prefix("/*") {
+            rest = comment.split_once("*/").map_or("", |(_, r)| r);
+        } else if let Some(literal) = rest.strip_prefix('\'') {
+            rest = literal.split_once('\'').map_or("", |(_, r)| r);
+        } else if rest.is_empty() {
+            return words;
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "(),;'".contains(c))
+                .unwrap_or(rest.len());
+            words.push((depth, rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+}
+
+/// What the statement destroys: `DROP TABLE db.t`, `TRUNCATE db.t`, `DELETE without WHERE
+/// from db.t` or `UPDATE without WHERE of db.t`. `None` for other statements.
+pub fn destructive(query: &str) -> Option<String> {
+    let (depths, words): (Vec<usize>, Vec<String>) = words(query).into_iter().unzip();
+    let lower: Vec<String> = words.iter().map(|w| w.to_lowercase()).collect();
+    // WHERE of a subquery does not limit the rows of the statement
+    let has_where = lower
+        .iter()
+        .zip(&depths)
+        .any(|(w, depth)| w == "where" && *depth == 0);
+    // name after the optional words
+    let target = |from: usize, optional: &[&str]| -> String {
+        let mut idx = from;
+        while lower
+            .get(idx)
+            .is_some_and(|w| optional.contains(&w.as_str()))
+        {
+            idx += 1;
+        }
+        words.get(idx).cloned().unwrap_or_default()
+    };
+    match lower.first()?.as_str() {
+        "drop" => {
+            let kind = words.get(1)?.to_uppercase();
+            Some(format!("DROP {} {}", kind, target(2, &["if", "exists"])))
+        }
+        "truncate" => Some(format!(
+            "TRUNCATE {}",
+            target(1, &["table", "if", "exists"])
+        )),
+        "delete" if !has_where => Some(format!(
+            "DELETE without WHERE from {}",
+            target(1, &["from"])
+        )),
+        "update" if !has_where => Some(format!("UPDATE without WHERE of {}", target(1, &[]))),
+        _ => None,
+    }
+}
+
+/// Queries estimated to read more than the limits are refused or confirmed by the user.
+/// Enabled by any of the profile keys `max_scan_bytes`, `max_scan_partitions` (Impala,
+/// Snowflake) and `max_cost` (Oracle).
+#[derive(Debug)]
+pub struct CostGuard {
+    pub max_bytes: Option<u64>,
+    pub max_partitions: Option<u64>,
+    pub max_cost: Option<f64>,
+    /// Ask the plugin instead of refusing, profile key `cost_guard = confirm`.
+    pub confirm: bool,
+}
+
+impl CostGuard {
+    pub fn from_profile(profile: &Profile) -> Result<Option<Self>, Error> {
+        let max_bytes = profile
+            .get("max_scan_bytes")
+            .map(|v| {
+                parse_size(v).ok_or_else(|| {
+                    anyhow!(
+                        "Profile {}: max_scan_bytes must be a size, e.g. 500GB",
+                        profile.name
+                    )
+                })
+            })
+            .transpose()?;
+        let max_cost = profile
+            .get("max_cost")
+            .map(|v| {
+                v.parse::<f64>()
+                    .map_err(|_| anyhow!("Profile {}: max_cost must be a number", profile.name))
+            })
+            .transpose()?;
+        let guard = CostGuard {
+            max_bytes,
+            max_partitions: profile.get_u64("max_scan_partitions")?,
+            max_cost,
+            confirm: match profile.get("cost_guard") {
+                None | Some("refuse") => false,
+                Some("confirm") => true,
+                Some(_) => {
+                    return Err(anyhow!(
+                        "Profile {}: cost_guard must be refuse or confirm",
+                        profile.name
+                    ))
+                }
+            },
+        };
+        if guard.max_bytes.is_none() && guard.max_partitions.is_none() && guard.max_cost.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(guard))
+    }
+
+    /// Only queries are explained, other statements are not checked.
+    pub fn applies(query: &str) -> bool {
+        matches!(keyword(query).as_str(), "select" | "with")
+    }
+
+    /// Why the query is too expensive, `None` if it is within the limits.
+    pub fn exceeded(&self, estimate: &ScanEstimate) -> Option<String> {
+        let mut reasons = Vec::new();
+        if let (Some(bytes), Some(max)) = (estimate.bytes, self.max_bytes) {
+            if bytes > max {
+                reasons.push(format!(
+                    "scan of {} exceeds max_scan_bytes {}",
+                    format_size(bytes),
+                    format_size(max)
+                ));
+            }
+        }
+        if let (Some(partitions), Some(max)) = (estimate.partitions, self.max_partitions) {
+            if partitions > max {
+                reasons.push(format!(
+                    "scan of {} partitions exceeds max_scan_partitions {}",
+                    partitions, max
+                ));
+            }
+        }
+        if let (Some(cost), Some(max)) = (estimate.cost, self.max_cost) {
+            if cost > max {
+                reasons.push(format!("cost {} exceeds max_cost {}", cost, max));
+            }
+        }
+        if reasons.is_empty() {
+            None
+        } else {
+            Some(format!("Estimated {}", reasons.join(", ")))
+        }
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KB", "MB", "GB", "TB"] {
+        if size < 1024.0 {
+            return format!("{:.2}{}", size, unit);
+        }
+        size /= 1024.0;
+    }
+    format!("{:.2}PB", size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_statements() {
+        assert_eq!(classify("SELECT * FROM t"), StatementKind::Read);
+        assert_eq!(classify("select 1"), StatementKind::Read);
+        assert_eq!(classify("(select 1) union (select 2)"), StatementKind::Read);
+        assert_eq!(classify("show tables"), StatementKind::Read);
+        assert_eq!(
+            classify("alter session set nls_date_format = 'YYYY'"),
+            StatementKind::Read
+        );
+        assert_eq!(
+            classify("ALTER TABLE t ADD COLUMNS (a INT)"),
+            StatementKind::Write
+        );
+        assert_eq!(classify("insert into t values (1)"), StatementKind::Write);
+        assert_eq!(classify("Drop Table t"), StatementKind::Write);
+        assert_eq!(classify("call p()"), StatementKind::Write);
+    }
+
+    #[test]
+    fn classifies_with_by_its_statement() {
+        assert_eq!(
+            classify("WITH a AS (SELECT 1 x) SELECT * FROM a"),
+            StatementKind::Read
+        );
+        assert_eq!(
+            classify("with a as (select 1 x) insert into t select * from a"),
+            StatementKind::Write
+        );
+        assert_eq!(
+            classify("WITH a AS (SELECT 1 x)\nINSERT OVERWRITE t SELECT * FROM a"),
+            StatementKind::Write
+        );
+    }
+
+    #[test]
+    fn skips_comments_before_keyword() {
+        assert_eq!(classify("-- load\nselect 1"), StatementKind::Read);
+        assert_eq!(classify("/* select */ delete from t"), StatementKind::Write);
+        assert_eq!(
+            classify("/* a */ -- b\n /* c */ SELECT 1"),
+            StatementKind::Read
+        );
+        assert_eq!(
+            classify("-- select\nupdate t set a = 1"),
+            StatementKind::Write
+        );
+        assert_eq!(keyword("  /* x */ (SELECT 1)"), "select");
+    }
+
+    #[test]
+    fn finds_destructive_statements() {
+        assert_eq!(
+            destructive("drop table if exists db.t").as_deref(),
+            Some("DROP TABLE db.t")
+        );
+        assert_eq!(
+            destructive("TRUNCATE TABLE db.t").as_deref(),
+            Some("TRUNCATE db.t")
+        );
+        assert_eq!(
+            destructive("delete from db.t").as_deref(),
+            Some("DELETE without WHERE from db.t")
+        );
+        assert_eq!(destructive("DELETE FROM db.t WHERE id = 1"), None);
+        assert_eq!(destructive("update t set a = 1 where id = 1"), None);
+        assert_eq!(destructive("select * from t"), None);
+    }
+
+    #[test]
+    fn counts_only_where_of_statement() {
+        assert_eq!(
+            destructive("UPDATE t SET a = (SELECT b FROM u WHERE u.id = 1)").as_deref(),
+            Some("UPDATE without WHERE of t")
+        );
+        assert_eq!(
+            destructive("delete from t where id in (select id from u where u.x = 1)"),
+            None
+        );
+        assert_eq!(
+            destructive("UPDATE t SET a = (SELECT max(b) FROM u WHERE u.id = t.id) WHERE a > 0"),
+            None
+        );
+        // WHERE in literals and comments is not counted either
+        assert_eq!(
+            destructive("update t set a = 'where' -- where\n").as_deref(),
+            Some("UPDATE without WHERE of t")
+        );
+    }
+}
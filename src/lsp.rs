@@ -0,0 +1,885 @@
+//! Minimal language server over stdio: completion of schema, table and column names,
+//! hover with column types and diagnostics from `EXPLAIN` of the statements. The statements
+//! are explained when the document is opened and saved, not while it is typed: a change
+//! only keeps the errors of the unchanged statements.
+//!
+//! Metadata comes from the schema cache, schemas which are not cached yet are loaded
+//! through the catalog functions of the connection and saved to the cache.
+//! [`serve`] is generic over the engine and the streams, so it runs with any `ConnectionFn`.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error};
+use serde_json::{json, Value};
+
+use crate::common::ConnectionFn;
+use crate::guard;
+use crate::schema_cache::{SchemaCache, Table};
+use crate::script::{split_spans, ScriptItem};
+
+const METHOD_NOT_FOUND: i64 = -32601;
+const SEVERITY_ERROR: i64 = 1;
+const KIND_FIELD: i64 = 5;
+const KIND_CLASS: i64 = 7;
+const KIND_MODULE: i64 = 9;
+
+/// Words after which a table name is not followed by an alias.
+const KEYWORDS: &[&str] = &[
+    "where",
+    "join",
+    "inner",
+    "left",
+    "right",
+    "full",
+    "outer",
+    "cross",
+    "on",
+    "using",
+    "group",
+    "order",
+    "having",
+    "limit",
+    "union",
+    "minus",
+    "except",
+    "intersect",
+    "select",
+    "set",
+    "values",
+    "lateral",
+];
+/// Statements which can be explained.
+const EXPLAINABLE: &[&str] = &["select", "with", "insert", "upsert", "update", "delete"];
+
+pub fn serve<C: ConnectionFn>(
+    client: &C,
+    cache_path: &Path,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<(), Error> {
+    let mut server = Server {
+        client,
+        cache_path: cache_path.to_path_buf(),
+        cache: SchemaCache::load(cache_path)?,
+        documents: HashMap::new(),
+        explained: HashMap::new(),
+    };
+
+    while let Some(message) = read_message(&mut input)? {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let result = match method {
+            "initialize" => Some(server.initialize()),
+            "shutdown" => Some(Value::Null),
+            "exit" => return Ok(()),
+            "textDocument/completion" => Some(server.completion(params)),
+            "textDocument/hover" => Some(server.hover(params)),
+            "textDocument/didOpen" => {
+                let doc = &params["textDocument"];
+                server.documents.insert(
+                    uri(params).to_string(),
+                    doc["text"].as_str().unwrap_or_default().to_string(),
+                );
+                let diagnostics = server.diagnostics(uri(params), true);
+                write_message(&mut output, &publish(uri(params), diagnostics))?;
+                None
+            }
+            "textDocument/didChange" => {
+                // full document sync
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    server
+                        .documents
+                        .insert(uri(params).to_string(), text.to_string());
+                    let diagnostics = server.diagnostics(uri(params), false);
+                    write_message(&mut output, &publish(uri(params), diagnostics))?;
+                }
+                None
+            }
+            "textDocument/didSave" => {
+                // objects may have changed on the server since the statements were explained
+                server.explained.remove(uri(params));
+                let diagnostics = server.diagnostics(uri(params), true);
+                write_message(&mut output, &publish(uri(params), diagnostics))?;
+                None
+            }
+            "textDocument/didClose" => {
+                server.documents.remove(uri(params));
+                server.explained.remove(uri(params));
+                write_message(&mut output, &publish(uri(params), Vec::new()))?;
+                None
+            }
+            _ => None,
+        };
+
+        // notifications have no id and no response
+        let id = &message["id"];
+        if id.is_null() {
+            continue;
+        }
+        let response = match result {
+            Some(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            None => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": METHOD_NOT_FOUND, "message": format!("Unknown method {}", method) },
+            }),
+        };
+        write_message(&mut output, &response)?;
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> Result<Option<Value>, Error> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let length = content_length.ok_or_else(|| anyhow!("Message without Content-Length"))?;
+    let mut content = vec![0; length];
+    input.read_exact(&mut content)?;
+    Ok(Some(serde_json::from_slice(&content)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> Result<(), Error> {
+    let content = message.to_string();
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()?;
+    Ok(())
+}
+
+fn uri(params: &Value) -> &str {
+    params["textDocument"]["uri"].as_str().unwrap_or_default()
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+struct Server<'a, C> {
+    client: &'a C,
+    cache_path: PathBuf,
+    cache: SchemaCache,
+    documents: HashMap<String, String>,
+    /// Errors of `EXPLAIN` by the statement text per document, `None` if it succeeded.
+    explained: HashMap<String, HashMap<String, Option<String>>>,
+}
+
+/// Table referenced by the statement, `alias` is the name used in the statement.
+struct TableRef {
+    schema: String,
+    table: String,
+    alias: String,
+}
+
+impl<C: ConnectionFn> Server<'_, C> {
+    fn initialize(&mut self) -> Value {
+        if self.cache.schemas.is_empty() {
+            // completions of schema names, their tables are loaded on demand
+            if self.cache.load_schema_names(self.client).is_ok() {
+                self.save_cache();
+            }
+        }
+        json!({
+            "capabilities": {
+                "textDocumentSync": { "openClose": true, "change": 1, "save": { "includeText": false } },
+                "completionProvider": { "triggerCharacters": ["."] },
+                "hoverProvider": true,
+            },
+            "serverInfo": { "name": "rust_jcsql_sub" },
+        })
+    }
+
+    fn completion(&mut self, params: &Value) -> Value {
+        let Some((statement, offset)) = self.statement_at(params) else {
+            return json!([]);
+        };
+        let (qualifier, _) = word_before(&statement, offset);
+        let refs = self.table_refs(&statement);
+
+        let mut items = Vec::new();
+        match qualifier {
+            Some(qualifier) => {
+                if let Some(table) = self.resolve_alias(&refs, &qualifier) {
+                    items.extend(columns(table));
+                } else if self.ensure_schema(&qualifier) {
+                    if let Some(schema) = self
+                        .cache
+                        .find_schema(&qualifier)
+                        .and_then(|name| self.cache.schemas.get(name))
+                    {
+                        items.extend(
+                            schema
+                                .tables
+                                .iter()
+                                .map(|(name, table)| item(name, KIND_CLASS, &table.table_type)),
+                        );
+                    }
+                }
+            }
+            None => {
+                items.extend(
+                    self.cache
+                        .schemas
+                        .keys()
+                        .map(|name| item(name, KIND_MODULE, "schema")),
+                );
+                for table_ref in &refs {
+                    if let Some(table) = self.table(&table_ref.schema, &table_ref.table) {
+                        items.extend(columns(table));
+                    }
+                }
+            }
+        }
+        Value::Array(items)
+    }
+
+    fn hover(&mut self, params: &Value) -> Value {
+        let Some((statement, offset)) = self.statement_at(params) else {
+            return Value::Null;
+        };
+        let (qualifier, word) = word_at(&statement, offset);
+        if word.is_empty() {
+            return Value::Null;
+        }
+        let refs = self.table_refs(&statement);
+
+        let text = match qualifier {
+            // alias.column
+            Some(qualifier) if self.resolve_alias(&refs, &qualifier).is_some() => self
+                .resolve_alias(&refs, &qualifier)
+                .and_then(|table| column_info(table, &word))
+                .map(|col_type| format!("{}.{}: {}", qualifier, word, col_type)),
+            // schema.table
+            Some(qualifier) => {
+                self.ensure_schema(&qualifier);
+                self.table(&qualifier, &word)
+                    .map(|table| table_info(&qualifier, &word, table))
+            }
+            None => {
+                let column = refs.iter().find_map(|r| {
+                    self.table(&r.schema, &r.table)
+                        .and_then(|table| column_info(table, &word))
+                        .map(|col_type| {
+                            format!("{}: {} ({}.{})", word, col_type, r.schema, r.table)
+                        })
+                });
+                column
+                    .or_else(|| {
+                        refs.iter()
+                            .find(|r| r.table.eq_ignore_ascii_case(&word))
+                            .and_then(|r| {
+                                self.table(&r.schema, &r.table)
+                                    .map(|table| table_info(&r.schema, &r.table, table))
+                            })
+                    })
+                    .or_else(|| {
+                        self.cache
+                            .find_schema(&word)
+                            .and_then(|name| self.cache.schemas.get(name).map(|s| (name, s)))
+                            .map(|(name, schema)| match schema.is_loaded() {
+                                true => format!("schema {}: {} tables", name, schema.tables.len()),
+                                false => format!("schema {}", name),
+                            })
+                    })
+            }
+        };
+        match text {
+            Some(text) => json!({ "contents": { "kind": "plaintext", "value": text } }),
+            None => Value::Null,
+        }
+    }
+
+    /// Errors of `EXPLAIN` of each statement in the document, only the statements which
+    /// were not explained yet are sent to the server, and only with `explain`.
+    fn diagnostics(&mut self, uri: &str, explain: bool) -> Vec<Value> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+        let Ok(items) = split_spans(text) else {
+            return Vec::new();
+        };
+        let mut previous = self.explained.remove(uri).unwrap_or_default();
+        let mut explained = HashMap::new();
+        let mut diagnostics = Vec::new();
+        for (span, item) in items {
+            let ScriptItem::Query(query) = item else {
+                continue;
+            };
+            // variables are known only when the script runs
+            if !EXPLAINABLE.contains(&guard::keyword(&query).as_str()) || query.contains('&') {
+                continue;
+            }
+            let error = match previous.remove(&query) {
+                Some(error) => error,
+                // explained on save
+                None if !explain => continue,
+                None => self
+                    .client
+                    .explain(&query, &[], None)
+                    .err()
+                    .map(|e| e.to_string()),
+            };
+            if let Some(message) = &error {
+                diagnostics.push(json!({
+                    "range": range(text, span),
+                    "severity": SEVERITY_ERROR,
+                    "source": "jcsql",
+                    "message": message,
+                }));
+            }
+            explained.insert(query, error);
+        }
+        self.explained.insert(uri.to_string(), explained);
+        diagnostics
+    }
+
+    /// Statement under the cursor and the cursor offset in it.
+    fn statement_at(&self, params: &Value) -> Option<(String, usize)> {
+        let text = self.documents.get(uri(params))?;
+        let position = &params["position"];
+        let offset = offset_at(
+            text,
+            position["line"].as_u64()? as usize,
+            position["character"].as_u64()? as usize,
+        );
+        split_spans(text)
+            .ok()?
+            .into_iter()
+            .find_map(|(span, item)| match item {
+                ScriptItem::Query(_) if span.start <= offset && offset <= span.end => {
+                    Some((text[span.start..span.end].to_string(), offset - span.start))
+                }
+                _ => None,
+            })
+    }
+
+    /// Tables after `FROM` and `JOIN`, unqualified ones are looked up in the cached schemas.
+    fn table_refs(&mut self, statement: &str) -> Vec<TableRef> {
+        let tokens = tokenize(statement);
+        let mut refs = Vec::new();
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let token = tokens[idx].to_lowercase();
+            idx += 1;
+            if token != "from" && token != "join" {
+                continue;
+            }
+            // comma separated list of tables
+            while let Some(name) = tokens.get(idx) {
+                idx += 1;
+                let mut alias = name.rsplit('.').next().unwrap_or(name).to_string();
+                if tokens
+                    .get(idx)
+                    .is_some_and(|t| t.eq_ignore_ascii_case("as"))
+                {
+                    idx += 1;
+                }
+                if let Some(next) = tokens.get(idx) {
+                    if is_identifier(next) && !KEYWORDS.contains(&next.to_lowercase().as_str()) {
+                        alias = next.clone();
+                        idx += 1;
+                    }
+                }
+                if let Some((schema, table)) = self.resolve_table(name) {
+                    refs.push(TableRef {
+                        schema,
+                        table,
+                        alias,
+                    });
+                }
+                if tokens.get(idx).map(String::as_str) != Some(",") {
+                    break;
+                }
+                idx += 1;
+            }
+        }
+        refs
+    }
+
+    /// Cached schema and table names of `schema.table` or `table`.
+    fn resolve_table(&mut self, name: &str) -> Option<(String, String)> {
+        match name.rsplit_once('.') {
+            Some((schema, table)) => {
+                self.ensure_schema(schema);
+                let schema = self.cache.find_schema(schema)?.to_string();
+                let (table, _) = self.cache.schemas[&schema].find_table(table)?;
+                Some((schema, table.to_string()))
+            }
+            None => self.cache.schemas.iter().find_map(|(schema, s)| {
+                s.find_table(name)
+                    .map(|(table, _)| (schema.clone(), table.to_string()))
+            }),
+        }
+    }
+
+    fn resolve_alias(&self, refs: &[TableRef], alias: &str) -> Option<&Table> {
+        refs.iter()
+            .find(|r| r.alias.eq_ignore_ascii_case(alias))
+            .and_then(|r| self.table(&r.schema, &r.table))
+    }
+
+    fn table(&self, schema: &str, table: &str) -> Option<&Table> {
+        let schema = self.cache.schemas.get(self.cache.find_schema(schema)?)?;
+        schema.find_table(table).map(|(_, t)| t)
+    }
+
+    /// Loads the schema on first use, `false` if there is no such schema.
+    fn ensure_schema(&mut self, name: &str) -> bool {
+        let loaded = self
+            .cache
+            .find_schema(name)
+            .and_then(|name| self.cache.schemas.get(name))
+            .is_some_and(|s| s.is_loaded());
+        if loaded {
+            return true;
+        }
+        match self.cache.ensure_schema(self.client, name) {
+            Ok(found) => {
+                if found {
+                    self.save_cache();
+                }
+                found
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn save_cache(&self) {
+        // the cache is an optimization, completions work without it
+        let _ = self.cache.save(&self.cache_path);
+    }
+}
+
+fn item(label: &str, kind: i64, detail: &str) -> Value {
+    json!({ "label": label, "kind": kind, "detail": detail })
+}
+
+fn columns(table: &Table) -> impl Iterator<Item = Value> + '_ {
+    table
+        .columns
+        .iter()
+        .map(|c| item(&c.name, KIND_FIELD, &c.col_type))
+}
+
+fn column_info<'t>(table: &'t Table, name: &str) -> Option<&'t str> {
+    table
+        .columns
+        .iter()
+        .find(|c| c.name.eq_ignore_ascii_case(name))
+        .map(|c| c.col_type.as_str())
+}
+
+fn table_info(schema: &str, name: &str, table: &Table) -> String {
+    let columns: Vec<String> = table
+        .columns
+        .iter()
+        .map(|c| format!("  {} {}", c.name, c.col_type))
+        .collect();
+    format!(
+        "{} {}.{}\n{}",
+        table.table_type,
+        schema,
+        name,
+        columns.join("\n")
+    )
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_' || ch == '$'
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.chars().all(is_ident_char)
+}
+
+/// Identifiers with their qualifiers (`schema.table`), commas; quoted strings and comments
+/// are skipped.
+fn tokenize(statement: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut quote_started = false;
+    let mut chars = statement.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\'' {
+            quote_started = !quote_started;
+        }
+        if !quote_started && (is_ident_char(ch) || ch == '.') {
+            current.push(ch);
+            continue;
+        }
+        if !current.is_empty() {
+            tokens.push(std::mem::take(&mut current));
+        }
+        if quote_started {
+            continue;
+        }
+        match (ch, chars.peek()) {
+            (',', _) => tokens.push(",".to_string()),
+            ('-', Some('-')) => {
+                chars.find(|ch| *ch == '\n');
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut last = ' ';
+                chars.find(|ch| std::mem::replace(&mut last, *ch) == '*' && *ch == '/');
+            }
+            _ => {}
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+/// Qualifier and the part of the word before the cursor, e.g. `(Some("t"), "na")` for `t.na|`.
+fn word_before(text: &str, offset: usize) -> (Option<String>, String) {
+    let before = &text[..offset];
+    let word_start = before
+        .rfind(|ch: char| !is_ident_char(ch))
+        .map_or(0, |idx| idx + 1);
+    let word = before[word_start..].to_string();
+    let qualifier = before[..word_start].strip_suffix('.').map(|rest| {
+        let start = rest
+            .rfind(|ch: char| !is_ident_char(ch))
+            .map_or(0, |idx| idx + 1);
+        rest[start..].to_string()
+    });
+    (qualifier.filter(|q| !q.is_empty()), word)
+}
+
+/// Qualifier and the whole word under the cursor.
+fn word_at(text: &str, offset: usize) -> (Option<String>, String) {
+    let word_end = text[offset..]
+        .find(|ch: char| !is_ident_char(ch))
+        .map_or(text.len(), |idx| offset + idx);
+    word_before(text, word_end)
+}
+
+/// Byte offset of the LSP position, characters are counted in UTF-16 code units.
+fn offset_at(text: &str, line: usize, character: usize) -> usize {
+    let line_start: usize = text.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (idx, ch) in text[line_start..].char_indices() {
+        if units >= character || ch == '\n' {
+            return line_start + idx;
+        }
+        units += ch.len_utf16();
+    }
+    text.len()
+}
+
+fn position_at(text: &str, offset: usize) -> Value {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+    json!({
+        "line": before.matches('\n').count(),
+        "character": before[line_start..].encode_utf16().count(),
+    })
+}
+
+fn range(text: &str, span: Range<usize>) -> Value {
+    json!({ "start": position_at(text, span.start), "end": position_at(text, span.end) })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::common::eng::{ColDesc, Row};
+    use crate::common::{Catalog, Engines};
+
+    /// Engine with one schema `sales` and table `orders`, `EXPLAIN` fails on `missing`.
+    #[derive(Default)]
+    struct MockClient {
+        executed: RefCell<Vec<String>>,
+    }
+
+    struct MockCursor {
+        rows: Vec<Row>,
+    }
+
+    fn result(cols: &[&str], rows: &[&[&str]]) -> (Vec<ColDesc>, Option<MockCursor>) {
+        let cols = cols
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| ColDesc::new(idx, name.to_string(), "Varchar".to_string()))
+            .collect();
+        let rows = rows
+            .iter()
+            .map(|row| row.iter().map(|v| Some((*v).into())).collect())
+            .collect();
+        (cols, Some(MockCursor { rows }))
+    }
+
+    impl ConnectionFn for MockClient {
+        type Cursor<'a> = MockCursor;
+
+        fn execute(
+            &self,
+            q: &str,
+            _params: &[String],
+            _fetch_num: i32,
+            _timeout: Option<u64>,
+        ) -> Result<(Vec<ColDesc>, Option<MockCursor>), Error> {
+            self.executed.borrow_mut().push(q.to_string());
+            if q.contains("missing") {
+                return Err(anyhow!("Could not resolve table reference: 'missing'"));
+            }
+            Ok(result(&["Explain String"], &[&["PLAN-ROOT SINK"]]))
+        }
+
+        fn browse(
+            &self,
+            what: &Catalog,
+            _fetch_num: i32,
+        ) -> Result<(Vec<ColDesc>, Option<MockCursor>), Error> {
+            Ok(match what {
+                Catalog::Schemas => result(&["table_schem"], &[&["sales"]]),
+                Catalog::Tables(_) => result(
+                    &["table_schem", "table_name", "table_type"],
+                    &[&["sales", "orders", "TABLE"]],
+                ),
+                Catalog::Columns(_) => result(
+                    &["table_schem", "table_name", "column_name", "type_name"],
+                    &[
+                        &["sales", "orders", "id", "BIGINT"],
+                        &["sales", "orders", "amount", "DECIMAL(10,2)"],
+                    ],
+                ),
+                _ => result(&[], &[]),
+            })
+        }
+
+        fn fetch(&self, c: &mut MockCursor, _fetch_num: i32) -> Result<(Vec<Row>, bool), Error> {
+            Ok((std::mem::take(&mut c.rows), true))
+        }
+
+        fn engine(&self) -> Engines {
+            Engines::Impala
+        }
+
+        fn set_autocommit(&self, _on: bool) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn commit(&self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn rollback(&self) -> Result<(), Error> {
+            Ok(())
+        }
+
+        fn dump_cell(
+            &self,
+            _q: &str,
+            _params: &[String],
+            _row: usize,
+            _col: u16,
+            _out: &mut dyn Write,
+        ) -> Result<u64, Error> {
+            Err(anyhow!("not supported"))
+        }
+    }
+
+    const URI: &str = "file:///work/report.sql";
+
+    fn frame(message: Value) -> String {
+        let content = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+    }
+
+    fn request(id: i64, method: &str, params: Value) -> String {
+        frame(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+    }
+
+    fn notification(method: &str, params: Value) -> String {
+        frame(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn position(line: usize, character: usize) -> Value {
+        json!({ "textDocument": { "uri": URI }, "position": { "line": line, "character": character } })
+    }
+
+    /// Runs the server over the messages, returns its messages.
+    fn run(client: &MockClient, messages: &[String]) -> Vec<Value> {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let cache = std::env::temp_dir().join(format!(
+            "jcsql-lsp-{}-{}.json",
+            std::process::id(),
+            RUNS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_file(&cache);
+        let mut output = Vec::new();
+        serve(client, &cache, Cursor::new(messages.concat()), &mut output).unwrap();
+        let _ = std::fs::remove_file(&cache);
+
+        let mut input = Cursor::new(output);
+        let mut res = Vec::new();
+        while let Some(message) = read_message(&mut input).unwrap() {
+            res.push(message);
+        }
+        res
+    }
+
+    fn response(messages: &[Value], id: i64) -> &Value {
+        &messages.iter().find(|m| m["id"] == id).unwrap()["result"]
+    }
+
+    fn published(messages: &[Value]) -> Vec<&Vec<Value>> {
+        messages
+            .iter()
+            .filter(|m| m["method"] == "textDocument/publishDiagnostics")
+            .map(|m| m["params"]["diagnostics"].as_array().unwrap())
+            .collect()
+    }
+
+    fn explains(client: &MockClient) -> Vec<String> {
+        client
+            .executed
+            .borrow()
+            .iter()
+            .filter_map(|q| q.strip_prefix("EXPLAIN "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn serves_completion_hover_and_diagnostics() {
+        let text = "select o.id from sales.orders o;\nselect * from missing;\n";
+        let changed = "select o.id, o.amount from sales.orders o;\nselect * from missing;\n";
+        let client = MockClient::default();
+        let messages = run(
+            &client,
+            &[
+                request(1, "initialize", json!({})),
+                notification(
+                    "textDocument/didOpen",
+                    json!({ "textDocument": { "uri": URI, "text": text } }),
+                ),
+                request(2, "textDocument/completion", position(0, 9)),
+                request(3, "textDocument/hover", position(0, 10)),
+                request(4, "textDocument/completion", position(1, 9)),
+                notification(
+                    "textDocument/didChange",
+                    json!({ "textDocument": { "uri": URI }, "contentChanges": [{ "text": changed }] }),
+                ),
+                notification(
+                    "textDocument/didSave",
+                    json!({ "textDocument": { "uri": URI } }),
+                ),
+                request(5, "shutdown", Value::Null),
+                request(6, "textDocument/formatting", json!({})),
+                notification("exit", Value::Null),
+            ],
+        );
+
+        let capabilities = &response(&messages, 1)["capabilities"];
+        assert_eq!(capabilities["hoverProvider"], true);
+
+        // `o.` completes the columns of the aliased table
+        let labels: Vec<&str> = response(&messages, 2)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, ["id", "amount"]);
+
+        assert_eq!(response(&messages, 3)["contents"]["value"], "o.id: BIGINT");
+
+        // schemas are completed in a statement without known tables
+        let labels: Vec<&str> = response(&messages, 4)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels, ["sales"]);
+
+        let published = published(&messages);
+        assert_eq!(published.len(), 3);
+        for diagnostics in &published {
+            assert_eq!(diagnostics.len(), 1);
+            assert_eq!(diagnostics[0]["range"]["start"]["line"], 1);
+            assert!(diagnostics[0]["message"]
+                .as_str()
+                .unwrap()
+                .contains("missing"));
+        }
+
+        // the change keeps the error of the unchanged statement without explaining, the save
+        // explains all of them
+        assert_eq!(
+            explains(&client),
+            [
+                "select o.id from sales.orders o",
+                "select * from missing",
+                "select o.id, o.amount from sales.orders o",
+                "select * from missing",
+            ]
+        );
+
+        let error = &messages.iter().find(|m| m["id"] == 6).unwrap()["error"];
+        assert_eq!(error["code"], METHOD_NOT_FOUND);
+    }
+
+    #[test]
+    fn skips_statements_which_are_not_explained() {
+        let text = "-- report\ndrop table t;\nselect &col from t;\n/* q */ (select 1);\n";
+        let client = MockClient::default();
+        run(
+            &client,
+            &[
+                notification(
+                    "textDocument/didOpen",
+                    json!({ "textDocument": { "uri": URI, "text": text } }),
+                ),
+                notification("exit", Value::Null),
+            ],
+        );
+        assert_eq!(explains(&client), ["/* q */ (select 1)"]);
+    }
+
+    #[test]
+    fn tokenizes_without_comments() {
+        let statement = "select a, 'x -- y' -- from b\nfrom /* join c, */ s.t, u /*/ v */ -- w";
+        assert_eq!(
+            tokenize(statement),
+            ["select", "a", ",", "from", "s.t", ",", "u"]
+        );
+        assert_eq!(tokenize("select 1 /* unclosed from t"), ["select", "1"]);
+    }
+}
@@ -0,0 +1,185 @@
+//! Patterns of the `find` command and the marking of the matching values.
+
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+
+use crate::common::eng::Row;
+
+/// What the values are searched for.
+pub enum Pattern {
+    /// Lowercase text found ignoring case.
+    Text(String),
+    Regex(Regex),
+}
+
+/// Rows of the grid with the matching values marked as `»value«`.
+pub struct Found {
+    pub grid: Vec<Vec<String>>,
+    /// `match row:column` of each value, numbered as the grid.
+    pub positions: Vec<String>,
+    pub matched_rows: usize,
+}
+
+impl Pattern {
+    /// `/regex/` or `/regex/i` ignoring case, otherwise text.
+    pub fn parse(pattern: &str) -> Result<Self> {
+        let regex = pattern.strip_prefix('/').and_then(|p| {
+            p.strip_suffix("/i")
+                .map(|p| (p, true))
+                .or_else(|| p.strip_suffix('/').map(|p| (p, false)))
+        });
+        match regex {
+            Some((regex, icase)) => RegexBuilder::new(regex)
+                .case_insensitive(icase)
+                .build()
+                .map(Pattern::Regex)
+                .map_err(|e| anyhow!("Invalid regex /{}/: {}", regex, e)),
+            None if pattern.is_empty() => Err(anyhow!("Nothing to find")),
+            None => Ok(Pattern::Text(pattern.to_lowercase())),
+        }
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        match self {
+            Pattern::Text(text) => value.to_lowercase().contains(text.as_str()),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+
+    /// Matches the values of the rows, NULL never matches, and marks the matching values in
+    /// the grid of their shown text. Rows without them are left out unless `all`.
+    pub fn mark(&self, rows: &[Row], shown: Vec<Vec<String>>, all: bool) -> Found {
+        let mut found = Found {
+            grid: Vec::new(),
+            positions: Vec::new(),
+            matched_rows: 0,
+        };
+        for (row, mut shown_row) in rows.iter().zip(shown) {
+            let mut matched = false;
+            for (col, value) in row.iter().enumerate() {
+                if value.as_deref().is_some_and(|v| self.is_match(v)) {
+                    matched = true;
+                    found
+                        .positions
+                        .push(format!("match {}:{}", found.grid.len() + 1, col + 1));
+                    shown_row[col] = format!("»{}«", shown_row[col]);
+                }
+            }
+            found.matched_rows += matched as usize;
+            if matched || all {
+                found.grid.push(shown_row);
+            }
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::eng::Value;
+    use crate::printing::ValueDisplay;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter()
+            .map(|row| row.iter().map(|v| v.to_string()).collect())
+            .collect()
+    }
+
+    fn mark(pattern: &Pattern, values: &[&[&str]], all: bool) -> Found {
+        let shown = rows(values);
+        let values: Vec<Row> = values
+            .iter()
+            .map(|row| row.iter().map(|v| Some((*v).into())).collect())
+            .collect();
+        pattern.mark(&values, shown, all)
+    }
+
+    #[test]
+    fn finds_text_ignoring_case() {
+        let pattern = Pattern::parse("York").unwrap();
+        assert!(pattern.is_match("New york"));
+        assert!(pattern.is_match("YORKSHIRE"));
+        assert!(!pattern.is_match("Yor k"));
+        // slashes only around the whole pattern make a regex
+        assert!(Pattern::parse("a/b").unwrap().is_match("A/B"));
+        assert!(Pattern::parse("/a").unwrap().is_match("/A"));
+        assert!(Pattern::parse("").is_err());
+    }
+
+    #[test]
+    fn finds_regex() {
+        let pattern = Pattern::parse(r"/^ORD-\d+$/").unwrap();
+        assert!(pattern.is_match("ORD-42"));
+        assert!(!pattern.is_match("ord-42"));
+        assert!(!pattern.is_match("ORD-42a"));
+        assert!(Pattern::parse(r"/^ord-\d+$/i").unwrap().is_match("ORD-42"));
+        assert!(Pattern::parse("/colou?r|grey/")
+            .unwrap()
+            .is_match("gray color"));
+        assert!(Pattern::parse("//").unwrap().is_match("anything"));
+
+        let e = Pattern::parse("/(a/").err().unwrap();
+        assert!(e.to_string().starts_with("Invalid regex /(a/"), "{}", e);
+    }
+
+    #[test]
+    fn matches_in_linear_time() {
+        let pattern = Pattern::parse("/(a|a)*b/").unwrap();
+        assert!(!pattern.is_match(&"a".repeat(10_000)));
+        let pattern = Pattern::parse("/(x+x+)+y/").unwrap();
+        assert!(!pattern.is_match(&"x".repeat(10_000)));
+    }
+
+    #[test]
+    fn marks_matching_values() {
+        let pattern = Pattern::parse("/^a/").unwrap();
+        let found = mark(&pattern, &[&["ab", "b"], &["c", "d"], &["e", "ax"]], false);
+        assert_eq!(found.grid, rows(&[&["»ab«", "b"], &["e", "»ax«"]]));
+        assert_eq!(found.positions, ["match 1:1", "match 2:2"]);
+        assert_eq!(found.matched_rows, 2);
+
+        let found = mark(&pattern, &[&["ab", "ac"], &["c", "d"], &["e", "ax"]], true);
+        assert_eq!(
+            found.grid,
+            rows(&[&["»ab«", "»ac«"], &["c", "d"], &["e", "»ax«"]])
+        );
+        assert_eq!(found.positions, ["match 1:1", "match 1:2", "match 3:2"]);
+        assert_eq!(found.matched_rows, 2);
+
+        let found = mark(&pattern, &[&["b"]], false);
+        assert!(found.grid.is_empty() && found.positions.is_empty());
+    }
+
+    #[test]
+    fn does_not_match_markers() {
+        let values: Vec<Row> = vec![
+            vec![
+                None,
+                Some("".into()),
+                Some(Value::lossy("a\u{FFFD}".to_string())),
+            ],
+            vec![Some("NULL".into()), Some("''".into()), Some("x".into())],
+        ];
+        let shown = ValueDisplay::default().render_rows(&values);
+        assert_eq!(shown[0], ["∅", "''", "[!] a\u{FFFD}"]);
+
+        let found = Pattern::parse("∅")
+            .unwrap()
+            .mark(&values, shown.clone(), false);
+        assert!(found.positions.is_empty());
+        let found = Pattern::parse("''")
+            .unwrap()
+            .mark(&values, shown.clone(), false);
+        assert_eq!(found.positions, ["match 1:2"]);
+        assert_eq!(found.grid, rows(&[&["NULL", "»''«", "x"]]));
+        let found = Pattern::parse("[!]")
+            .unwrap()
+            .mark(&values, shown.clone(), true);
+        assert!(found.positions.is_empty());
+        assert_eq!(found.grid, shown);
+        // the lossy value is matched by its text and shown with the marker
+        let found = Pattern::parse("/^a/").unwrap().mark(&values, shown, false);
+        assert_eq!(found.grid, rows(&[&["∅", "''", "»[!] a\u{FFFD}«"]]));
+    }
+}
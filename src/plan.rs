@@ -0,0 +1,538 @@
+//! Query plans of the engines, rendered as an indented tree with the estimates of each
+//! operation highlighted.
+
+use anyhow::{anyhow, Error};
+use serde_json::Value;
+
+use crate::common::eng::Row;
+use crate::common::Engines;
+
+const INDENT: &str = "   ";
+
+/// Operation of the plan, nodes are kept in tree preorder with their depth.
+#[derive(Debug, Default)]
+pub struct PlanNode {
+    pub depth: usize,
+    pub operation: String,
+    pub details: Vec<String>,
+    /// Estimated rows, cost, memory etc.
+    pub estimates: Vec<(String, String)>,
+}
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub header: Vec<String>,
+    pub nodes: Vec<PlanNode>,
+    pub footer: Vec<String>,
+}
+
+/// What the query is going to read, as estimated by the plan.
+#[derive(Debug, Default)]
+pub struct ScanEstimate {
+    pub bytes: Option<u64>,
+    pub partitions: Option<u64>,
+    pub cost: Option<f64>,
+}
+
+/// `6.12KB`, `5.3TB` or `12M`, multiples of 1024.
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let number = text[..split].parse::<f64>().ok()?;
+    let power = match text[split..].trim().to_uppercase().trim_end_matches('B') {
+        "" => 0,
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        "P" => 5,
+        _ => return None,
+    };
+    Some((number * 1024f64.powi(power)) as u64)
+}
+
+/// Statements run before the plan query, and the plan query itself.
+pub fn explain_statements(engine: Engines, q: &str, level: Option<u8>) -> (Vec<String>, String) {
+    match engine {
+        Engines::Impala => (
+            level
+                .map(|l| vec![format!("SET EXPLAIN_LEVEL={}", l)])
+                .unwrap_or_default(),
+            format!("EXPLAIN {}", q),
+        ),
+        Engines::Oracle => (
+            vec![format!("EXPLAIN PLAN FOR {}", q)],
+            "SELECT plan_table_output FROM TABLE(DBMS_XPLAN.DISPLAY())".to_string(),
+        ),
+        Engines::Snowflake => (Vec::new(), format!("EXPLAIN USING JSON {}", q)),
+    }
+}
+
+/// Query option set by the statements of [`explain_statements`] and its default, the option
+/// is set back to its previous value after the plan.
+pub fn explain_option(engine: Engines, level: Option<u8>) -> Option<(&'static str, &'static str)> {
+    match (engine, level) {
+        (Engines::Impala, Some(_)) => Some(("EXPLAIN_LEVEL", "1")),
+        _ => None,
+    }
+}
+
+/// Value of the option in the rows of Impala `SET`: option, value and level.
+pub fn option_value(rows: &[Row], name: &str) -> Option<String> {
+    rows.iter()
+        .find(|row| {
+            row.first()
+                .and_then(Option::as_deref)
+                .is_some_and(|o| o.eq_ignore_ascii_case(name))
+        })
+        .and_then(|row| row.get(1)?.as_ref())
+        .map(|value| value.text.clone())
+}
+
+impl Plan {
+    /// Parses the rows returned by the plan query of [`explain_statements`].
+    pub fn parse(engine: Engines, rows: &[Row]) -> Result<Self, Error> {
+        let lines: Vec<&str> = rows
+            .iter()
+            .filter_map(|r| r.first()?.as_deref())
+            .flat_map(|r| r.lines())
+            .collect();
+        match engine {
+            Engines::Impala => Ok(parse_impala(&lines)),
+            Engines::Oracle => Ok(parse_oracle(&lines)),
+            Engines::Snowflake => parse_snowflake(&lines.join("\n")),
+        }
+    }
+
+    /// Impala: `partitions=` and `size=` of the scans, Oracle: cost of the statement,
+    /// Snowflake: assigned partitions and bytes.
+    pub fn scan_estimate(&self, engine: Engines) -> ScanEstimate {
+        let mut estimate = ScanEstimate::default();
+        let add = |value: &mut Option<u64>, n: u64| *value = Some(value.unwrap_or(0) + n);
+        match engine {
+            Engines::Impala => {
+                // `HDFS partitions=365/1826 files=1460 size=1.52TB`, stored statistics of
+                // higher explain levels also have `size=` of the whole table
+                let values = self
+                    .nodes
+                    .iter()
+                    .flat_map(|node| &node.details)
+                    .filter(|detail| {
+                        detail
+                            .split_whitespace()
+                            .any(|v| v.starts_with("partitions="))
+                    })
+                    .flat_map(|detail| detail.split_whitespace());
+                for value in values {
+                    if let Some(size) = value.strip_prefix("size=").and_then(parse_size) {
+                        add(&mut estimate.bytes, size);
+                    }
+                    if let Some(partitions) = value
+                        .strip_prefix("partitions=")
+                        .and_then(|p| p.split('/').next())
+                        .and_then(|p| p.parse::<u64>().ok())
+                    {
+                        add(&mut estimate.partitions, partitions);
+                    }
+                }
+            }
+            Engines::Oracle => {
+                // `1234  (1)`: cost and CPU percent of the root operation
+                estimate.cost = self
+                    .nodes
+                    .first()
+                    .and_then(|node| node.estimates.iter().find(|(name, _)| name == "cost"))
+                    .and_then(|(_, cost)| cost.split_whitespace().next())
+                    .and_then(|cost| cost.parse::<f64>().ok());
+            }
+            Engines::Snowflake => {
+                for (name, value) in self.nodes.iter().flat_map(|node| &node.estimates) {
+                    match name.as_str() {
+                        "bytes" => {
+                            if let Ok(bytes) = value.parse::<u64>() {
+                                add(&mut estimate.bytes, bytes);
+                            }
+                        }
+                        "partitions" => {
+                            if let Some(Ok(partitions)) =
+                                value.split('/').next().map(str::parse::<u64>)
+                            {
+                                add(&mut estimate.partitions, partitions);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        estimate
+    }
+
+    pub fn render(&self) -> Vec<String> {
+        let mut res = self.header.clone();
+        for node in &self.nodes {
+            let indent = INDENT.repeat(node.depth);
+            let estimates = node
+                .estimates
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect::<Vec<String>>()
+                .join(", ");
+            if estimates.is_empty() {
+                res.push(format!("{}{}", indent, node.operation));
+            } else {
+                res.push(format!("{}{}  ** {} **", indent, node.operation, estimates));
+            }
+            for detail in &node.details {
+                res.push(format!("{}{}{}", indent, INDENT, detail));
+            }
+        }
+        res.extend(self.footer.iter().cloned());
+        res
+    }
+}
+
+/// `03:AGGREGATE [FINALIZE]` or `PLAN-ROOT SINK`
+fn is_impala_node(text: &str) -> bool {
+    if text.starts_with("PLAN-ROOT SINK") {
+        return true;
+    }
+    let digits = text.chars().take_while(|c| c.is_ascii_digit()).count();
+    digits > 0 && text[digits..].starts_with(':')
+}
+
+/// Impala draws the tree itself: nodes in one column are a chain of children, `|--` starts
+/// a branch of the node above it.
+fn parse_impala(lines: &[&str]) -> Plan {
+    let mut plan = Plan::default();
+    // column and depth of the last node of each open chain
+    let mut chains: Vec<(usize, usize)> = Vec::new();
+    for line in lines {
+        let text_start = line
+            .find(|c: char| c != '|' && c != ' ' && c != '-')
+            .unwrap_or(line.len());
+        let text = line[text_start..].trim_end();
+        if text.is_empty() {
+            continue;
+        }
+        if !is_impala_node(text) {
+            match plan.nodes.last_mut() {
+                Some(node) => {
+                    for (name, key) in [("rows", "cardinality="), ("mem", "mem-estimate=")] {
+                        if let Some(value) = text
+                            .split_whitespace()
+                            .find_map(|part| part.strip_prefix(key))
+                        {
+                            node.estimates.push((name.to_string(), value.to_string()));
+                        }
+                    }
+                    node.details.push(text.to_string());
+                }
+                None => plan.header.push(text.to_string()),
+            }
+            continue;
+        }
+
+        while chains.last().is_some_and(|(col, _)| *col > text_start) {
+            chains.pop();
+        }
+        let depth = match chains.last_mut() {
+            Some((col, depth)) if *col == text_start => {
+                *depth += 1;
+                *depth
+            }
+            Some((_, depth)) => {
+                let depth = *depth + 1;
+                chains.push((text_start, depth));
+                depth
+            }
+            None => {
+                chains.push((text_start, 0));
+                0
+            }
+        };
+        plan.nodes.push(PlanNode {
+            depth,
+            operation: text.to_string(),
+            ..Default::default()
+        });
+    }
+    plan
+}
+
+/// `DBMS_XPLAN.DISPLAY` table, depth is the indent of the operation.
+fn parse_oracle(lines: &[&str]) -> Plan {
+    let mut plan = Plan::default();
+    let mut columns: Vec<String> = Vec::new();
+    for line in lines {
+        let is_row = line.starts_with('|');
+        if is_row && columns.is_empty() {
+            columns = line.split('|').map(|c| c.trim().to_string()).collect();
+            continue;
+        }
+        if !is_row {
+            let text = line.trim_end();
+            // table borders
+            if text.starts_with("---") {
+                continue;
+            }
+            if columns.is_empty() {
+                plan.header.push(text.to_string());
+            } else {
+                plan.footer.push(text.to_string());
+            }
+            continue;
+        }
+
+        let mut node = PlanNode::default();
+        let mut id = String::new();
+        let mut name = String::new();
+        for (column, cell) in columns.iter().zip(line.split('|')) {
+            let value = cell.trim();
+            match column.as_str() {
+                "Operation" => {
+                    // one space of padding, one more per level
+                    node.depth = (cell.len() - cell.trim_start().len()).saturating_sub(1);
+                    node.operation = value.to_string();
+                }
+                "Name" => name = value.to_string(),
+                // `*` marks operations with predicates in the footer
+                "Id" => id = value.replace(' ', ""),
+                "Rows" | "Bytes" | "Time" | "TempSpc" if !value.is_empty() => node
+                    .estimates
+                    .push((column.to_lowercase(), value.to_string())),
+                c if c.starts_with("Cost") && !value.is_empty() => {
+                    node.estimates.push(("cost".to_string(), value.to_string()))
+                }
+                _ => {}
+            }
+        }
+        node.operation = format!("{}:{} {}", id, node.operation, name)
+            .trim_end()
+            .to_string();
+        plan.nodes.push(node);
+    }
+    plan
+}
+
+/// `EXPLAIN USING JSON`: operations of each step with their parents.
+fn parse_snowflake(json: &str) -> Result<Plan, Error> {
+    let value: Value = serde_json::from_str(json)?;
+    let mut plan = Plan::default();
+    if let Some(stats) = value["GlobalStats"].as_object() {
+        plan.header.push(
+            stats
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join(" "),
+        );
+    }
+    let steps = value["Operations"]
+        .as_array()
+        .ok_or_else(|| anyhow!("Plan has no Operations"))?;
+    for (step_idx, step) in steps.iter().enumerate() {
+        let operations = step.as_array().cloned().unwrap_or_default();
+        if steps.len() > 1 {
+            plan.header.push(format!("Step {}", step_idx + 1));
+        }
+        let parents = |op: &Value| -> Vec<i64> {
+            match (&op["parent"], &op["parentOperators"]) {
+                (Value::Number(p), _) => p.as_i64().into_iter().collect(),
+                (_, Value::Array(ps)) => ps.iter().filter_map(Value::as_i64).collect(),
+                _ => Vec::new(),
+            }
+        };
+        let roots = operations.iter().filter(|op| parents(op).is_empty());
+        let mut stack: Vec<(&Value, usize)> = roots.rev().map(|op| (op, 0)).collect();
+        while let Some((op, depth)) = stack.pop() {
+            plan.nodes.push(snowflake_node(op, depth));
+            let id = op["id"].as_i64();
+            let children = operations
+                .iter()
+                .filter(|child| id.is_some_and(|id| parents(child).contains(&id)));
+            stack.extend(children.rev().map(|child| (child, depth + 1)));
+        }
+    }
+    Ok(plan)
+}
+
+fn snowflake_node(op: &Value, depth: usize) -> PlanNode {
+    let mut node = PlanNode {
+        depth,
+        operation: op["operation"].as_str().unwrap_or_default().to_string(),
+        ..Default::default()
+    };
+    if let Some(objects) = op["objects"].as_array() {
+        let objects: Vec<&str> = objects.iter().filter_map(Value::as_str).collect();
+        node.operation = format!("{} {}", node.operation, objects.join(", "));
+    }
+    if let Some(expressions) = op["expressions"].as_array() {
+        node.details.extend(
+            expressions
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string),
+        );
+    }
+    if let (Some(assigned), Some(total)) = (
+        op["partitionsAssigned"].as_i64(),
+        op["partitionsTotal"].as_i64(),
+    ) {
+        node.estimates
+            .push(("partitions".to_string(), format!("{}/{}", assigned, total)));
+    }
+    if let Some(bytes) = op["bytesAssigned"].as_i64() {
+        node.estimates
+            .push(("bytes".to_string(), bytes.to_string()));
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guard::CostGuard;
+
+    const IMPALA_AGGREGATE: &str = include_str!("../tests/fixtures/impala_explain_aggregate.txt");
+    const IMPALA_JOIN: &str = include_str!("../tests/fixtures/impala_explain_join.txt");
+    const ORACLE: &str = include_str!("../tests/fixtures/oracle_xplan.txt");
+
+    /// Rows of the plan query, a line each.
+    fn plan(engine: Engines, text: &str) -> Plan {
+        let rows: Vec<Row> = text.lines().map(|l| vec![Some(l.into())]).collect();
+        Plan::parse(engine, &rows).unwrap()
+    }
+
+    fn guard(bytes: Option<&str>, partitions: Option<u64>, cost: Option<f64>) -> CostGuard {
+        CostGuard {
+            max_bytes: bytes.and_then(parse_size),
+            max_partitions: partitions,
+            max_cost: cost,
+            confirm: false,
+        }
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("12"), Some(12));
+        assert_eq!(parse_size("6.5KB"), Some(6656));
+        assert_eq!(parse_size("2 GB"), Some(2 << 30));
+        assert_eq!(parse_size("1.5T"), Some(3 << 39));
+        assert_eq!(parse_size("12XB"), None);
+        assert_eq!(parse_size("GB"), None);
+    }
+
+    #[test]
+    fn parses_impala_tree() {
+        let plan = plan(Engines::Impala, IMPALA_AGGREGATE);
+        assert_eq!(plan.header.len(), 4);
+        let nodes: Vec<(usize, &str)> = plan
+            .nodes
+            .iter()
+            .map(|n| (n.depth, n.operation.as_str()))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (0, "PLAN-ROOT SINK"),
+                (1, "04:EXCHANGE [UNPARTITIONED]"),
+                (2, "03:AGGREGATE [FINALIZE]"),
+                (3, "02:EXCHANGE [HASH(o.region)]"),
+                (4, "01:AGGREGATE [STREAMING]"),
+                (5, "00:SCAN HDFS [sales.orders o]"),
+            ]
+        );
+        let scan = plan.nodes.last().unwrap();
+        assert_eq!(scan.estimates, [("rows".to_string(), "4.21G".to_string())]);
+    }
+
+    #[test]
+    fn parses_impala_branches() {
+        let plan = plan(Engines::Impala, IMPALA_JOIN);
+        let nodes: Vec<(usize, &str)> = plan
+            .nodes
+            .iter()
+            .map(|n| (n.depth, n.operation.as_str()))
+            .collect();
+        assert_eq!(
+            nodes,
+            [
+                (0, "PLAN-ROOT SINK"),
+                (1, "05:EXCHANGE [UNPARTITIONED]"),
+                (2, "02:HASH JOIN [INNER JOIN, BROADCAST]"),
+                (3, "04:EXCHANGE [BROADCAST]"),
+                (4, "01:SCAN HDFS [sales.customers c]"),
+                (3, "00:SCAN HDFS [sales.orders o]"),
+            ]
+        );
+    }
+
+    #[test]
+    fn estimates_impala_scan() {
+        let estimate = plan(Engines::Impala, IMPALA_AGGREGATE).scan_estimate(Engines::Impala);
+        assert_eq!(estimate.partitions, Some(365));
+        assert_eq!(estimate.bytes, parse_size("1.52TB"));
+
+        // both scans, without the sizes of the stored statistics
+        let estimate = plan(Engines::Impala, IMPALA_JOIN).scan_estimate(Engines::Impala);
+        assert_eq!(estimate.partitions, Some(1827));
+        assert_eq!(
+            estimate.bytes,
+            Some(parse_size("7.61TB").unwrap() + parse_size("245.31MB").unwrap())
+        );
+        assert_eq!(estimate.cost, None);
+    }
+
+    #[test]
+    fn guards_impala_thresholds() {
+        let estimate = plan(Engines::Impala, IMPALA_JOIN).scan_estimate(Engines::Impala);
+        assert_eq!(
+            guard(Some("500GB"), None, None)
+                .exceeded(&estimate)
+                .unwrap(),
+            "Estimated scan of 7.61TB exceeds max_scan_bytes 500.00GB"
+        );
+        assert_eq!(
+            guard(None, Some(1000), None).exceeded(&estimate).unwrap(),
+            "Estimated scan of 1827 partitions exceeds max_scan_partitions 1000"
+        );
+        let both = guard(Some("1TB"), Some(100), None)
+            .exceeded(&estimate)
+            .unwrap();
+        assert!(both.contains("max_scan_bytes") && both.contains("max_scan_partitions"));
+
+        assert!(guard(Some("10TB"), Some(2000), None)
+            .exceeded(&estimate)
+            .is_none());
+        // the cost limit is for Oracle plans
+        assert!(guard(None, None, Some(1.0)).exceeded(&estimate).is_none());
+
+        let estimate = plan(Engines::Impala, IMPALA_AGGREGATE).scan_estimate(Engines::Impala);
+        assert!(guard(Some("2TB"), Some(365), None)
+            .exceeded(&estimate)
+            .is_none());
+        assert!(guard(None, Some(364), None).exceeded(&estimate).is_some());
+    }
+
+    #[test]
+    fn guards_oracle_cost() {
+        let plan = plan(Engines::Oracle, ORACLE);
+        assert_eq!(plan.nodes.len(), 2);
+        assert_eq!(plan.nodes[1].operation, "*1:TABLE ACCESS FULL EMPLOYEES");
+        assert_eq!(plan.nodes[1].depth, 1);
+        let estimate = plan.scan_estimate(Engines::Oracle);
+        assert_eq!(estimate.cost, Some(48213.0));
+        assert_eq!(
+            guard(None, None, Some(10000.0))
+                .exceeded(&estimate)
+                .unwrap(),
+            "Estimated cost 48213 exceeds max_cost 10000"
+        );
+        assert!(guard(None, None, Some(50000.0))
+            .exceeded(&estimate)
+            .is_none());
+    }
+}
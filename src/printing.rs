@@ -1,4 +1,96 @@
+use std::cell::RefCell;
+use std::fs::File;
 use std::io::{self, Result, Write};
+use std::path::Path;
+
+use crate::common::eng::{ColDesc, Row, Value};
+
+/// Prefix of the values which were not valid UTF-8 and lost bytes in decoding.
+pub const LOSSY_MARKER: &str = "[!] ";
+
+// Output is per thread, so daemon sessions can write to their clients.
+thread_local! {
+    static OUTPUT: RefCell<Option<Box<dyn Write>>> = RefCell::new(None);
+    static SPOOL: RefCell<Option<File>> = const { RefCell::new(None) };
+}
+
+/// Starts teeing the output to the file, `None` stops it.
+pub fn spool(path: Option<&Path>) -> Result<()> {
+    let file = match path {
+        Some(path) => Some(File::create(path)?),
+        None => None,
+    };
+    SPOOL.with(|spool| *spool.borrow_mut() = file);
+    Ok(())
+}
+
+/// Sends the output of current thread to the writer instead of stdout, `None` restores stdout.
+pub fn redirect(output: Option<Box<dyn Write>>) {
+    OUTPUT.with(|out| *out.borrow_mut() = output);
+}
+
+/// Writes to stdout and to the spool file if any, the spool file gets the bytes also when
+/// the output fails.
+pub fn write_out(bytes: &[u8]) -> Result<()> {
+    let out = OUTPUT.with(|out| match out.borrow_mut().as_mut() {
+        Some(out) => out.write_all(bytes),
+        None => io::stdout().write_all(bytes),
+    });
+    let spool = SPOOL.with(|spool| match spool.borrow_mut().as_mut() {
+        Some(file) => file.write_all(bytes),
+        None => Ok(()),
+    });
+    out.and(spool)
+}
+
+/// Write errors are ignored: the daemon client may have disconnected, and the session
+/// with its connection must outlive it.
+pub fn print_line(line: &str) {
+    let _ = write_out(format!("{}\n", line).as_bytes());
+}
+
+/// How NULL, empty and whitespace-only values are shown, profile keys `null_display`
+/// and `empty_display`.
+#[derive(Debug)]
+pub struct ValueDisplay {
+    pub null: String,
+    pub empty: String,
+}
+
+impl Default for ValueDisplay {
+    fn default() -> Self {
+        ValueDisplay {
+            null: "∅".to_string(),
+            empty: "''".to_string(),
+        }
+    }
+}
+
+impl ValueDisplay {
+    /// Whitespace-only values are quoted with the escaped whitespace.
+    pub fn render(&self, value: Option<&str>) -> String {
+        match value {
+            None => self.null.clone(),
+            Some("") => self.empty.clone(),
+            Some(v) if v.trim().is_empty() => format!("'{}'", v.escape_default()),
+            Some(v) => v.to_string(),
+        }
+    }
+
+    /// Lossy values are prefixed with [`LOSSY_MARKER`].
+    pub fn render_value(&self, value: Option<&Value>) -> String {
+        match value {
+            Some(v) if v.lossy => format!("{}{}", LOSSY_MARKER, self.render(Some(v))),
+            v => self.render(v.map(|v| &**v)),
+        }
+    }
+
+    pub fn render_rows<'a>(&self, rows: impl IntoIterator<Item = &'a Row>) -> Vec<Vec<String>> {
+        rows.into_iter()
+            .map(|row| row.iter().map(|v| self.render_value(v.as_ref())).collect())
+            .collect()
+    }
+}
 
 #[derive(Debug)]
 pub struct CellSize {
@@ -82,16 +174,16 @@ impl CellLines {
     pub fn print(&self) -> Result<()> {
         let new_line_bytes = "\n".as_bytes();
         if !self.l_top.is_empty() {
-            io::stdout().write_all(self.l_top.as_bytes())?;
-            io::stdout().write_all(new_line_bytes)?;
+            write_out(self.l_top.as_bytes())?;
+            write_out(new_line_bytes)?;
         }
 
-        io::stdout().write_all(self.l_val.as_bytes())?;
-        io::stdout().write_all(new_line_bytes)?;
+        write_out(self.l_val.as_bytes())?;
+        write_out(new_line_bytes)?;
 
         if !self.l_bot.is_empty() {
-            io::stdout().write_all(self.l_bot.as_bytes())?;
-            io::stdout().write_all(new_line_bytes)?;
+            write_out(self.l_bot.as_bytes())?;
+            write_out(new_line_bytes)?;
         }
         Ok(())
     }
@@ -182,5 +274,157 @@ pub fn print_cells_line(lines: Vec<CellLines>) {
         res_line.l_val += i.l_val.as_str();
         res_line.l_bot += i.l_bot.as_str();
     }
-    res_line.print().unwrap()
+    // ignored as by print_line
+    let _ = res_line.print();
+}
+
+fn upd_data_col_max_lens(data_row: &[String], max_len: &mut [usize]) {
+    for (idx, i) in data_row.iter().enumerate() {
+        let chars_cnt = i.chars().count();
+        if chars_cnt > max_len[idx] {
+            max_len[idx] = chars_cnt
+        }
+    }
+}
+
+fn upd_header_col_max_lens(header_row: &[ColDesc], max_len: &mut [usize]) {
+    for (idx, i) in header_row.iter().enumerate() {
+        let chars_cnt = i.get_print_name().chars().count();
+        if chars_cnt > max_len[idx] {
+            max_len[idx] = chars_cnt
+        }
+    }
+}
+
+pub fn print_message(
+    start_msg: &Vec<String>,
+    print_buffer: Option<Vec<Vec<CellLines>>>,
+    end_msg: &Vec<String>,
+) {
+    for smsg in start_msg {
+        print_line(&format!("{}\n", smsg));
+    }
+
+    if let Some(print_buffer) = print_buffer {
+        let fetched = print_buffer.len();
+        for i in print_buffer {
+            print_cells_line(i);
+        }
+        print_line(&format!("\nFetched {} rows", fetched - 1));
+    }
+
+    for emsg in end_msg {
+        print_line(&format!("{}\n", emsg));
+    }
+}
+
+pub fn to_print_buffer(header: &Vec<ColDesc>, data: &Vec<Vec<String>>) -> Vec<Vec<CellLines>> {
+    let mut print_buffer: Vec<Vec<CellLines>> = Vec::new();
+    let mut col_max: Vec<usize> = vec![0; header.len()];
+
+    upd_header_col_max_lens(header, &mut col_max);
+    for i in data.iter() {
+        upd_data_col_max_lens(i, &mut col_max);
+    }
+
+    let mut print_row: Vec<CellLines> = Vec::new();
+    for (col_idx, cd) in header.iter().enumerate() {
+        print_row.push(cell_to_print(CellParams::new(
+            cd.get_print_name(),
+            cd.get_print_name().len(),
+            cd.get_print_name().chars().count(),
+            CellSize::new(col_max[col_idx], col_idx == 0, true, true, true),
+        )));
+    }
+    print_buffer.push(print_row);
+
+    let res_len = data.len();
+    for (row_idx, row) in data.iter().enumerate() {
+        let mut print_row: Vec<CellLines> = Vec::new();
+        for (col_idx, col) in row.iter().enumerate() {
+            print_row.push(cell_to_print(CellParams::new(
+                col.to_string(),
+                col.len(),
+                col.chars().count(),
+                CellSize::new(
+                    col_max[col_idx],
+                    col_idx == 0,
+                    true,
+                    false,
+                    row_idx == (res_len - 1),
+                ),
+            )));
+        }
+        print_buffer.push(print_row);
+    }
+    print_buffer
+}
+
+/// Rows as left aligned columns.
+pub fn align(rows: &[Vec<String>]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (idx, value) in row.iter().enumerate() {
+            let len = value.chars().count();
+            match widths.get_mut(idx) {
+                Some(width) => *width = (*width).max(len),
+                None => widths.push(len),
+            }
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{:width$}", value, width = width))
+                .collect::<Vec<String>>()
+                .join("  ")
+                .trim_end()
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_null_and_empty_values() {
+        let display = ValueDisplay::default();
+        assert_eq!(display.render(None), "∅");
+        assert_eq!(display.render(Some("NULL")), "NULL");
+        assert_eq!(display.render(Some("")), "''");
+        assert_eq!(display.render(Some("  ")), "'  '");
+        assert_eq!(display.render(Some("\t\n")), "'\\t\\n'");
+        assert_eq!(display.render(Some(" a ")), " a ");
+
+        let display = ValueDisplay {
+            null: "<null>".to_string(),
+            empty: String::new(),
+        };
+        let rows = vec![vec![
+            None,
+            Some("".into()),
+            Some("x".into()),
+            Some(Value::lossy("a\u{FFFD}".to_string())),
+        ]];
+        assert_eq!(
+            display.render_rows(&rows),
+            vec![vec!["<null>", "", "x", "[!] a\u{FFFD}"]]
+        );
+    }
+
+    #[test]
+    fn aligns_columns_by_chars() {
+        let rows = vec![
+            vec!["column".to_string(), "min".to_string(), "max".to_string()],
+            vec!["städte".to_string(), "∅".to_string(), "Zürich".to_string()],
+            vec!["id".to_string(), "10".to_string(), String::new()],
+        ];
+        assert_eq!(
+            align(&rows),
+            ["column  min  max", "städte  ∅    Zürich", "id      10"]
+        );
+    }
 }
@@ -0,0 +1,306 @@
+//! Cached metadata of the connection for editor autocompletion:
+//!
+//! ```json
+//! {
+//!   "updated_at": "2024-01-01T10:00:00+01:00",
+//!   "schemas": {
+//!     "sales": {
+//!       "updated_at": "2024-01-01T10:00:00+01:00",
+//!       "tables": {
+//!         "orders": { "type": "TABLE", "columns": [{ "name": "id", "type": "BIGINT" }] }
+//!       }
+//!     }
+//!   }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Error};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{eng::ColDesc, Catalog, ConnectionFn};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SchemaCache {
+    pub updated_at: String,
+    pub schemas: BTreeMap<String, Schema>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Schema {
+    pub updated_at: String,
+    pub tables: BTreeMap<String, Table>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Table {
+    #[serde(rename = "type")]
+    pub table_type: String,
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub col_type: String,
+}
+
+impl SchemaCache {
+    /// Empty cache if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        if !path.exists() {
+            return Ok(SchemaCache::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Cannot read schema cache {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("Broken schema cache {}, delete it", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Cannot write schema cache {}", path.display()))
+    }
+
+    /// Reloads the schemas from the catalog, all of them if `schemas` is empty.
+    /// Other schemas in the cache are kept.
+    pub fn refresh<C: ConnectionFn>(
+        &mut self,
+        client: &C,
+        schemas: &[String],
+    ) -> Result<(), Error> {
+        let schemas = if schemas.is_empty() {
+            let (cols, rows) = fetch_all(client, &Catalog::Schemas)?;
+            let schema_idx = column_index(&cols, "table_schem")?;
+            // the schemas which are gone are removed only on the full refresh
+            self.schemas.clear();
+            rows.into_iter()
+                .map(|mut r| r.swap_remove(schema_idx))
+                .collect()
+        } else {
+            schemas.to_vec()
+        };
+
+        for name in schemas {
+            let schema = load_schema(client, &name)?;
+            self.schemas.insert(name, schema);
+        }
+        self.updated_at = Local::now().to_rfc3339();
+        Ok(())
+    }
+
+    /// Adds names of the schemas which are not in the cache yet, without their tables.
+    pub fn load_schema_names<C: ConnectionFn>(&mut self, client: &C) -> Result<(), Error> {
+        let (cols, rows) = fetch_all(client, &Catalog::Schemas)?;
+        let schema_idx = column_index(&cols, "table_schem")?;
+        for mut row in rows {
+            self.schemas.entry(row.swap_remove(schema_idx)).or_default();
+        }
+        Ok(())
+    }
+
+    /// Name of the cached schema, case insensitive.
+    pub fn find_schema(&self, name: &str) -> Option<&str> {
+        self.schemas
+            .keys()
+            .find(|k| k.eq_ignore_ascii_case(name))
+            .map(String::as_str)
+    }
+
+    /// Loads tables of the schema if they are not cached yet. Returns `false` if there is
+    /// no such schema.
+    pub fn ensure_schema<C: ConnectionFn>(
+        &mut self,
+        client: &C,
+        name: &str,
+    ) -> Result<bool, Error> {
+        let name = self.find_schema(name).unwrap_or(name).to_string();
+        if self.schemas.get(&name).is_some_and(Schema::is_loaded) {
+            return Ok(true);
+        }
+        let schema = load_schema(client, &name)?;
+        if schema.tables.is_empty() && !self.schemas.contains_key(&name) {
+            return Ok(false);
+        }
+        self.schemas.insert(name, schema);
+        Ok(true)
+    }
+
+    pub fn tables_count(&self) -> usize {
+        self.schemas.values().map(|s| s.tables.len()).sum()
+    }
+}
+
+impl Schema {
+    /// Schemas listed by [`SchemaCache::load_schema_names`] have no tables loaded.
+    pub fn is_loaded(&self) -> bool {
+        !self.updated_at.is_empty()
+    }
+
+    /// Table by name, case insensitive.
+    pub fn find_table(&self, name: &str) -> Option<(&str, &Table)> {
+        self.tables
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(k, t)| (k.as_str(), t))
+    }
+}
+
+fn load_schema<C: ConnectionFn>(client: &C, name: &str) -> Result<Schema, Error> {
+    let mut schema = Schema {
+        updated_at: Local::now().to_rfc3339(),
+        tables: BTreeMap::new(),
+    };
+    let all_tables = format!("{}.%", name);
+
+    let (cols, rows) = fetch_all(client, &Catalog::Tables(Some(all_tables.clone())))?;
+    let (schema_idx, table_idx, type_idx) = (
+        column_index(&cols, "table_schem")?,
+        column_index(&cols, "table_name")?,
+        column_index(&cols, "table_type")?,
+    );
+    // `_` in the schema is a wildcard for the catalog functions
+    for row in rows
+        .iter()
+        .filter(|r| r[schema_idx].eq_ignore_ascii_case(name))
+    {
+        schema.tables.insert(
+            row[table_idx].clone(),
+            Table {
+                table_type: row[type_idx].clone(),
+                columns: Vec::new(),
+            },
+        );
+    }
+
+    let (cols, rows) = fetch_all(client, &Catalog::Columns(all_tables))?;
+    let (schema_idx, table_idx, column_idx, type_idx) = (
+        column_index(&cols, "table_schem")?,
+        column_index(&cols, "table_name")?,
+        column_index(&cols, "column_name")?,
+        column_index(&cols, "type_name")?,
+    );
+    for row in rows
+        .iter()
+        .filter(|r| r[schema_idx].eq_ignore_ascii_case(name))
+    {
+        if let Some(table) = schema.tables.get_mut(&row[table_idx]) {
+            table.columns.push(Column {
+                name: row[column_idx].clone(),
+                col_type: row[type_idx].clone(),
+            });
+        }
+    }
+    Ok(schema)
+}
+
+fn fetch_all<C: ConnectionFn>(
+    client: &C,
+    what: &Catalog,
+) -> Result<(Vec<ColDesc>, Vec<Vec<String>>), Error> {
+    let (cols, cursor) = client.browse(what, -1)?;
+    let rows = match cursor {
+        Some(mut cursor) => client.fetch(&mut cursor, -1)?.0,
+        None => Vec::new(),
+    };
+    // NULL names are empty
+    let rows = rows
+        .into_iter()
+        .map(|row| {
+            row.into_iter()
+                .map(|v| v.map(|v| v.text).unwrap_or_default())
+                .collect()
+        })
+        .collect();
+    Ok((cols, rows))
+}
+
+fn column_index(cols: &[ColDesc], name: &str) -> Result<usize, Error> {
+    cols.iter()
+        .position(|c| c.col_name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("Catalog result has no {} column", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(table_type: &str, columns: &[(&str, &str)]) -> Table {
+        Table {
+            table_type: table_type.to_string(),
+            columns: columns
+                .iter()
+                .map(|(name, col_type)| Column {
+                    name: name.to_string(),
+                    col_type: col_type.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn saves_and_loads() {
+        let dir = std::env::temp_dir().join(format!("jcsql-cache-{}", std::process::id()));
+        let path = dir.join("nested").join("profile.json");
+        let _ = fs::remove_dir_all(&dir);
+
+        // no file yet
+        let cache = SchemaCache::load(&path).unwrap();
+        assert!(cache.schemas.is_empty() && cache.updated_at.is_empty());
+
+        let mut cache = SchemaCache {
+            updated_at: "2024-01-01T10:00:00+01:00".to_string(),
+            schemas: BTreeMap::new(),
+        };
+        let mut sales = Schema {
+            updated_at: "2024-01-01T10:00:00+01:00".to_string(),
+            tables: BTreeMap::new(),
+        };
+        sales.tables.insert(
+            "orders".to_string(),
+            table("TABLE", &[("id", "BIGINT"), ("amount", "DECIMAL(10,2)")]),
+        );
+        sales
+            .tables
+            .insert("v_orders".to_string(), table("VIEW", &[]));
+        cache.schemas.insert("Sales".to_string(), sales);
+        // listed by name only, its tables are loaded on first use
+        cache.schemas.insert("hr".to_string(), Schema::default());
+        cache.save(&path).unwrap();
+
+        let loaded = SchemaCache::load(&path).unwrap();
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(loaded.updated_at, cache.updated_at);
+        assert_eq!(loaded.tables_count(), 2);
+        assert_eq!(loaded.find_schema("SALES"), Some("Sales"));
+        let sales = &loaded.schemas["Sales"];
+        assert!(sales.is_loaded());
+        let (name, orders) = sales.find_table("ORDERS").unwrap();
+        assert_eq!((name, orders.table_type.as_str()), ("orders", "TABLE"));
+        let columns: Vec<(&str, &str)> = orders
+            .columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.col_type.as_str()))
+            .collect();
+        assert_eq!(columns, [("id", "BIGINT"), ("amount", "DECIMAL(10,2)")]);
+        assert!(!loaded.schemas["hr"].is_loaded());
+        assert!(loaded.find_schema("finance").is_none());
+    }
+
+    #[test]
+    fn reports_broken_file() {
+        let path = std::env::temp_dir().join(format!("jcsql-cache-{}.json", std::process::id()));
+        fs::write(&path, "{ \"schemas\": ").unwrap();
+        let e = SchemaCache::load(&path).unwrap_err();
+        let _ = fs::remove_file(&path);
+        assert!(e.to_string().starts_with("Broken schema cache"), "{}", e);
+    }
+}
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Error};
+
+/// SQL*Plus commands that are not supported by the client.
+const UNSUPPORTED: [&str; 19] = [
+    "accept",
+    "append",
+    "break",
+    "btitle",
+    "clear",
+    "column",
+    "compute",
+    "connect",
+    "disconnect",
+    "exit",
+    "host",
+    "pause",
+    "print",
+    "quit",
+    "repheader",
+    "save",
+    "ttitle",
+    "variable",
+    "whenever",
+];
+
+/// Client side commands, executed locally instead of being sent to the server.
+#[derive(Debug)]
+pub enum Directive {
+    Define(String, String),
+    Undefine(Vec<String>),
+    Include(String),
+    Set(SetOption),
+    Spool(Option<String>),
+    Prompt(String),
+}
+
+/// Client options changed by `SET`. Other `SET` statements are sent to the server.
+#[derive(Debug)]
+pub enum SetOption {
+    Fetch(i32),
+    Timing(bool),
+    /// Statement timeout in seconds, `0` or `off` disables it.
+    Timeout(Option<u64>),
+    /// Impala exec summary after the result.
+    Summary(bool),
+    /// Manual commit mode when off.
+    Autocommit(bool),
+    /// Column statistics under the grid.
+    Stats(bool),
+}
+
+impl Directive {
+    /// Parses a single script line. Returns `None` when the line is not a directive.
+    pub fn parse(line: &str) -> Result<Option<Self>, Error> {
+        let line = line.trim().trim_end_matches(';').trim_end();
+        if let Some(file) = line.strip_prefix('@') {
+            let file = file.trim_start_matches('@').trim();
+            if file.is_empty() {
+                return Err(anyhow!("Invalid directive: {}. Expected @file.sql", line));
+            }
+            return Ok(Some(Directive::Include(file.to_string())));
+        }
+
+        let (cmd, rest) = match line.split_once(char::is_whitespace) {
+            Some((cmd, rest)) => (cmd, rest.trim()),
+            None => (line, ""),
+        };
+
+        match cmd.to_lowercase().as_str() {
+            "def" | "define" => {
+                let (name, value) = rest.split_once('=').ok_or_else(|| {
+                    anyhow!("Invalid directive: {}. Expected DEFINE name = value", line)
+                })?;
+                let name = name.trim();
+                if name.is_empty() {
+                    return Err(anyhow!(
+                        "Invalid directive: {}. Variable name is empty",
+                        line
+                    ));
+                }
+                Ok(Some(Directive::Define(
+                    name.to_string(),
+                    unquote(value.trim()).to_string(),
+                )))
+            }
+            "undef" | "undefine" => {
+                if rest.is_empty() {
+                    return Err(anyhow!(
+                        "Invalid directive: {}. Expected UNDEFINE name",
+                        line
+                    ));
+                }
+                Ok(Some(Directive::Undefine(
+                    rest.split_whitespace().map(|n| n.to_string()).collect(),
+                )))
+            }
+            "source" => {
+                if rest.is_empty() {
+                    return Err(anyhow!("Invalid directive: {}. Expected SOURCE file", line));
+                }
+                Ok(Some(Directive::Include(unquote(rest).to_string())))
+            }
+            "set" => SetOption::parse(rest)
+                .map(|o| o.map(Directive::Set))
+                .map_err(|e| anyhow!("Invalid directive: {}. {}", line, e)),
+            "spo" | "spool" => match rest.to_lowercase().as_str() {
+                "" => Err(anyhow!("Invalid directive: {}. Expected SPOOL path", line)),
+                "off" => Ok(Some(Directive::Spool(None))),
+                _ => Ok(Some(Directive::Spool(Some(unquote(rest).to_string())))),
+            },
+            "pro" | "prompt" => Ok(Some(Directive::Prompt(rest.to_string()))),
+            other if UNSUPPORTED.contains(&other) => Err(anyhow!("Unknown directive: {}", line)),
+            _ => Ok(None),
+        }
+    }
+}
+
+impl SetOption {
+    fn parse(rest: &str) -> Result<Option<Self>, Error> {
+        let (name, value) = match rest.split_once(|c: char| c == '=' || c.is_whitespace()) {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => (rest, ""),
+        };
+
+        match name.to_lowercase().as_str() {
+            "fetch" => Ok(Some(SetOption::Fetch(
+                value
+                    .parse::<i32>()
+                    .map_err(|_| anyhow!("Expected SET fetch N"))?,
+            ))),
+            "timing" => Ok(Some(SetOption::Timing(
+                parse_on_off(value).ok_or_else(|| anyhow!("Expected SET timing on|off"))?,
+            ))),
+            "autocommit" => Ok(Some(SetOption::Autocommit(
+                parse_on_off(value).ok_or_else(|| anyhow!("Expected SET autocommit on|off"))?,
+            ))),
+            "summary" => Ok(Some(SetOption::Summary(
+                parse_on_off(value).ok_or_else(|| anyhow!("Expected SET summary on|off"))?,
+            ))),
+            "stats" => Ok(Some(SetOption::Stats(
+                parse_on_off(value).ok_or_else(|| anyhow!("Expected SET stats on|off"))?,
+            ))),
+            "timeout" => match value.to_lowercase().as_str() {
+                "off" | "0" => Ok(Some(SetOption::Timeout(None))),
+                _ => Ok(Some(SetOption::Timeout(Some(
+                    value
+                        .parse::<u64>()
+                        .map_err(|_| anyhow!("Expected SET timeout N|off"))?,
+                )))),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+fn parse_on_off(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" => Some(true),
+        "off" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    for q in ['\'', '"'] {
+        if value.len() >= 2 && value.starts_with(q) && value.ends_with(q) {
+            return &value[1..value.len() - 1];
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(line: &str) -> SetOption {
+        match Directive::parse(line).unwrap() {
+            Some(Directive::Set(option)) => option,
+            other => panic!("not a SET directive: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_set_options() {
+        assert!(matches!(set("SET fetch 500"), SetOption::Fetch(500)));
+        assert!(matches!(set("set fetch=20;"), SetOption::Fetch(20)));
+        assert!(matches!(set("set timing off"), SetOption::Timing(false)));
+        assert!(matches!(
+            set("SET TIMEOUT 30"),
+            SetOption::Timeout(Some(30))
+        ));
+        assert!(matches!(set("set timeout off"), SetOption::Timeout(None)));
+        assert!(matches!(set("set timeout 0"), SetOption::Timeout(None)));
+        assert!(matches!(set("set summary on"), SetOption::Summary(true)));
+        assert!(matches!(
+            set("set autocommit false"),
+            SetOption::Autocommit(false)
+        ));
+        assert!(matches!(set("set stats on"), SetOption::Stats(true)));
+    }
+
+    #[test]
+    fn rejects_invalid_set_values() {
+        assert!(Directive::parse("set fetch many").is_err());
+        assert!(Directive::parse("set timing maybe").is_err());
+        assert!(Directive::parse("set timeout soon").is_err());
+    }
+
+    #[test]
+    fn leaves_server_set_statements() {
+        assert!(Directive::parse("SET REQUEST_POOL=etl").unwrap().is_none());
+        assert!(Directive::parse("select 1").unwrap().is_none());
+    }
+
+    #[test]
+    fn parses_other_directives() {
+        assert!(matches!(
+            Directive::parse("DEFINE name = 'a b'").unwrap(),
+            Some(Directive::Define(n, v)) if n == "name" && v == "a b"
+        ));
+        assert!(matches!(
+            Directive::parse("@@sub/file.sql").unwrap(),
+            Some(Directive::Include(f)) if f == "sub/file.sql"
+        ));
+        assert!(matches!(
+            Directive::parse("spool off").unwrap(),
+            Some(Directive::Spool(None))
+        ));
+        assert!(Directive::parse("define x").is_err());
+        assert!(Directive::parse("whenever sqlerror exit").is_err());
+    }
+}
@@ -0,0 +1,49 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Context, Error};
+
+use super::directive::Directive;
+use super::location::Location;
+use super::splitter::{split_spans, ScriptItem};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Splits script read from `file` and replaces `@file` / `source file` directives with
+/// the content of included files. Paths are relative to the including file.
+pub fn load_script(
+    script: &str,
+    file: &Path,
+    base_dir: &Path,
+) -> Result<Vec<(Location, ScriptItem)>, Error> {
+    let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+    load(script, &file, base_dir, 0)
+}
+
+fn load(
+    script: &str,
+    file: &Path,
+    base_dir: &Path,
+    depth: usize,
+) -> Result<Vec<(Location, ScriptItem)>, Error> {
+    let mut res: Vec<(Location, ScriptItem)> = Vec::new();
+    for (span, item) in split_spans(script)? {
+        match item {
+            ScriptItem::Directive(Directive::Include(file)) => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(anyhow!(
+                        "Too many nested includes at {}, recursive include?",
+                        file
+                    ));
+                }
+                let path = base_dir.join(&file);
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("Cannot include {}", path.display()))?;
+                let dir = path.parent().unwrap_or(base_dir);
+                res.extend(load(&content, &path, dir, depth + 1)?);
+            }
+            other => res.push((Location::new(file, script, &span), other)),
+        }
+    }
+    Ok(res)
+}
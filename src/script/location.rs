@@ -0,0 +1,113 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/// Where the statement is in its file, lines and columns are 1-based.
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+}
+
+impl Location {
+    /// Location of the byte range of the script read from the file.
+    pub fn new(file: &Path, script: &str, span: &Range<usize>) -> Self {
+        let before = &script[..span.start];
+        let line_start = before.rfind('\n').map_or(0, |idx| idx + 1);
+        let line = before.matches('\n').count() + 1;
+        Location {
+            file: file.to_path_buf(),
+            line,
+            column: before[line_start..].chars().count() + 1,
+            end_line: line + script[span.clone()].matches('\n').count(),
+        }
+    }
+
+    /// Position in the file of the line and column in the statement.
+    pub fn resolve(&self, line: usize, column: Option<usize>) -> (usize, usize) {
+        let column = column.unwrap_or(1).max(1);
+        if line <= 1 {
+            (self.line, self.column + column - 1)
+        } else {
+            (self.line + line - 1, column)
+        }
+    }
+}
+
+/// Line and column in the statement reported by the engine: `ORA-06550: line 3, column 5`,
+/// Impala `Syntax error in line 2:` followed by the line and `^` under the column,
+/// or `at line 2`.
+pub fn error_position(msg: &str) -> Option<(usize, Option<usize>)> {
+    for (idx, _) in msg.match_indices("line ") {
+        if msg[..idx]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric())
+        {
+            continue;
+        }
+        let rest = &msg[idx + "line ".len()..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let Ok(line) = rest[..digits].parse::<usize>() else {
+            continue;
+        };
+        let rest = &rest[digits..];
+        let column = match rest.strip_prefix(", column ") {
+            Some(col) => col
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect::<String>()
+                .parse::<usize>()
+                .ok(),
+            // the caret is under the column in the second line after the message
+            None => rest
+                .lines()
+                .nth(2)
+                .filter(|l| l.trim() == "^")
+                .and_then(|l| l.find('^'))
+                .map(|idx| idx + 1),
+        };
+        return Some((line, column));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_oracle_position() {
+        let msg = "ORA-06550: line 3, column 5:\nPLS-00201: identifier 'X' must be declared";
+        assert_eq!(error_position(msg), Some((3, Some(5))));
+    }
+
+    #[test]
+    fn parses_impala_caret() {
+        let msg =
+            "AnalysisException: Syntax error in line 2:\nfrom tt where\n      ^\nEncountered: EOF";
+        assert_eq!(error_position(msg), Some((2, Some(7))));
+    }
+
+    #[test]
+    fn parses_line_only() {
+        assert_eq!(error_position("error at line 4"), Some((4, None)));
+        assert_eq!(error_position("no position; deadline 5"), None);
+        assert_eq!(error_position("nothing here"), None);
+    }
+
+    #[test]
+    fn resolves_in_file() {
+        let script = "select 1;\n\n  select\n  x from t;";
+        let start = script.find("select\n").unwrap();
+        let location = Location::new(Path::new("a.sql"), script, &(start..script.len() - 1));
+        assert_eq!(
+            (location.line, location.column, location.end_line),
+            (3, 3, 4)
+        );
+        assert_eq!(location.resolve(1, Some(4)), (3, 6));
+        assert_eq!(location.resolve(2, Some(3)), (4, 3));
+        assert_eq!(location.resolve(2, None), (4, 1));
+    }
+}
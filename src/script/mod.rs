@@ -0,0 +1,11 @@
+pub mod directive;
+pub mod loader;
+pub mod location;
+pub mod splitter;
+pub mod vars;
+
+pub use self::directive::{Directive, SetOption};
+pub use self::loader::load_script;
+pub use self::location::{error_position, Location};
+pub use self::splitter::{split_spans, ScriptItem};
+pub use self::vars::Vars;
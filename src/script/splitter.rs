@@ -0,0 +1,231 @@
+use std::ops::Range;
+
+use anyhow::Error;
+
+use super::directive::Directive;
+
+#[derive(Debug)]
+pub enum ScriptItem {
+    Query(String),
+    Directive(Directive),
+}
+
+/// Splits script into queries by `;`. Directives are recognized at the start of a line
+/// between queries and do not need a terminating `;`. Returns byte range of each item
+/// in the script, queries without surrounding whitespace. Text of whitespace and comments
+/// only is not a query.
+pub fn split_spans(script: &str) -> Result<Vec<(Range<usize>, ScriptItem)>, Error> {
+    let mut res: Vec<(Range<usize>, ScriptItem)> = Vec::new();
+    // `'` or `"` of the open literal or quoted identifier
+    let mut quote: Option<char> = None;
+    let mut block_comment = false;
+    // current has something besides whitespace and comments
+    let mut has_code = false;
+    let mut current = String::new();
+    // span of non-whitespace part of current
+    let mut start: Option<usize> = None;
+    let mut end = 0;
+    let mut line_start = 0;
+
+    for line in script.split_inclusive('\n') {
+        if quote.is_none() && !block_comment && !has_code {
+            if let Some(directive) = Directive::parse(line)? {
+                // comments before the directive are dropped
+                current.clear();
+                start = None;
+                res.push((
+                    line_start..line_start + line.trim_end().len(),
+                    ScriptItem::Directive(directive),
+                ));
+                line_start += line.len();
+                continue;
+            }
+        }
+
+        let mut line_comment = false;
+        // second character of `--`, `/*` or `*/`
+        let mut token_end = false;
+        let mut chars = line.char_indices().peekable();
+        while let Some((idx, ch)) = chars.next() {
+            let pos = line_start + idx;
+            let next = chars.peek().map(|(_, c)| *c);
+            let mut comment = true;
+            if std::mem::take(&mut token_end) || line_comment {
+            } else if block_comment {
+                if ch == '*' && next == Some('/') {
+                    block_comment = false;
+                    token_end = true;
+                }
+            } else if let Some(q) = quote {
+                // a doubled quote closes and opens again
+                if ch == q {
+                    quote = None;
+                }
+                comment = false;
+            } else if ch == '-' && next == Some('-') {
+                line_comment = true;
+                token_end = true;
+            } else if ch == '/' && next == Some('*') {
+                block_comment = true;
+                token_end = true;
+            } else if ch == ';' {
+                if has_code {
+                    let span = start.map_or(pos..pos, |start| start..end);
+                    res.push((span, ScriptItem::Query(current.trim().to_string())));
+                }
+                current.clear();
+                start = None;
+                has_code = false;
+                continue;
+            } else {
+                if ch == '\'' || ch == '"' {
+                    quote = Some(ch);
+                }
+                comment = false;
+            }
+            has_code |= !comment && !ch.is_whitespace();
+            current.push(ch);
+            if !ch.is_whitespace() {
+                start.get_or_insert(pos);
+                end = pos + ch.len_utf8();
+            }
+        }
+        line_start += line.len();
+    }
+    // rest of string
+    if has_code {
+        res.push((
+            start.unwrap_or_default()..end,
+            ScriptItem::Query(current.trim().to_string()),
+        ));
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queries(script: &str) -> Vec<String> {
+        split_spans(script)
+            .unwrap()
+            .into_iter()
+            .filter_map(|(_, item)| match item {
+                ScriptItem::Query(q) => Some(q),
+                ScriptItem::Directive(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_by_semicolon() {
+        assert_eq!(queries("select 1;\nselect 2;\n"), ["select 1", "select 2"]);
+        assert_eq!(queries("select 1;select 2"), ["select 1", "select 2"]);
+    }
+
+    #[test]
+    fn keeps_semicolon_in_literal() {
+        assert_eq!(
+            queries("select 'a;b', 'it''s;' from t;"),
+            ["select 'a;b', 'it''s;' from t"]
+        );
+    }
+
+    #[test]
+    fn ignores_quotes_and_semicolons_in_comments() {
+        assert_eq!(
+            queries("select 1 -- don't; stop\nfrom t;\nselect 2;"),
+            ["select 1 -- don't; stop\nfrom t", "select 2"]
+        );
+        assert_eq!(
+            queries("select /* it's; */ 1;\nselect 2;"),
+            ["select /* it's; */ 1", "select 2"]
+        );
+        assert_eq!(
+            queries("select 1 /* multi\nline; it's */;\nselect 2;"),
+            ["select 1 /* multi\nline; it's */", "select 2"]
+        );
+    }
+
+    #[test]
+    fn spans_point_at_queries() {
+        let script = "  select 1 ;\nDEFINE a = 1\n select 2;";
+        let items = split_spans(script).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(&script[items[0].0.clone()], "select 1");
+        assert!(matches!(
+            items[1].1,
+            ScriptItem::Directive(Directive::Define(..))
+        ));
+        assert_eq!(&script[items[2].0.clone()], "select 2");
+    }
+
+    #[test]
+    fn directive_only_between_queries() {
+        let items = split_spans("select\nprompt x\nfrom t;").unwrap();
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn drops_comments_after_last_query() {
+        assert_eq!(
+            queries(
+                "select 1; -- done
+"
+            ),
+            ["select 1"]
+        );
+        assert_eq!(
+            queries(
+                "select 1;
+/* the end;
+ */
+"
+            ),
+            ["select 1"]
+        );
+        let items = split_spans("select 1; -- done").unwrap();
+        assert_eq!(items.len(), 1);
+    }
+
+    #[test]
+    fn drops_empty_queries() {
+        assert_eq!(queries("select 1;;"), ["select 1"]);
+        assert_eq!(
+            queries(
+                ";
+ ; -- x
+;select 2;"
+            ),
+            ["select 2"]
+        );
+    }
+
+    #[test]
+    fn keeps_semicolon_in_quoted_identifier() {
+        assert_eq!(
+            queries("select \"a;b\", \"it's\" from t;select 2"),
+            ["select \"a;b\", \"it's\" from t", "select 2"]
+        );
+        assert_eq!(
+            queries("select 'say \"hi;' from t;"),
+            ["select 'say \"hi;' from t"]
+        );
+    }
+
+    #[test]
+    fn recognizes_directive_after_comment() {
+        let items = split_spans("-- report\nDEFINE a = 1\nselect &a;").unwrap();
+        assert_eq!(items.len(), 2);
+        assert!(matches!(
+            items[0].1,
+            ScriptItem::Directive(Directive::Define(..))
+        ));
+        assert!(matches!(&items[1].1, ScriptItem::Query(q) if q == "select &a"));
+
+        let items = split_spans("select 1; /* setup */\nSET autocommit off\nselect 2;").unwrap();
+        assert_eq!(items.len(), 3);
+        assert!(matches!(items[1].1, ScriptItem::Directive(_)));
+    }
+}
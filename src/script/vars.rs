@@ -0,0 +1,220 @@
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+/// Substitution variables (`&var`, `&&var`) and bind parameters (`:name`) of a script.
+/// Names are case insensitive, as in SQL*Plus.
+#[derive(Debug, Default)]
+pub struct Vars {
+    values: HashMap<String, String>,
+}
+
+impl Vars {
+    pub fn new(defined: &[(String, String)]) -> Self {
+        let mut vars = Vars::default();
+        for (name, value) in defined {
+            vars.define(name, value);
+        }
+        vars
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.values.insert(name.to_lowercase(), value.to_string());
+    }
+
+    pub fn undefine(&mut self, name: &str) {
+        self.values.remove(&name.to_lowercase());
+    }
+
+    fn get_or_prompt(
+        &mut self,
+        name: &str,
+        keep: bool,
+        prompt: &mut impl FnMut(&str) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        if let Some(value) = self.values.get(&name.to_lowercase()) {
+            return Ok(value.clone());
+        }
+        let value = prompt(name)?;
+        if keep {
+            self.define(name, &value);
+        }
+        Ok(value)
+    }
+
+    /// Textually replaces `&var` and `&&var` in query. Undefined variables are prompted,
+    /// `&&var` keeps the prompted value for the rest of the script.
+    /// A `.` right after the name terminates it and is removed.
+    pub fn substitute(
+        &mut self,
+        query: &str,
+        prompt: &mut impl FnMut(&str) -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        let chars: Vec<char> = query.chars().collect();
+        let mut res = String::with_capacity(query.len());
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] != '&' {
+                res.push(chars[i]);
+                i += 1;
+                continue;
+            }
+
+            let keep = chars.get(i + 1) == Some(&'&');
+            let name_start = if keep { i + 2 } else { i + 1 };
+            let name_end = name_end(&chars, name_start);
+            if name_end == name_start {
+                res.push(chars[i]);
+                i += 1;
+                continue;
+            }
+
+            let name: String = chars[name_start..name_end].iter().collect();
+            res.push_str(&self.get_or_prompt(&name, keep, prompt)?);
+            i = name_end;
+            if chars.get(i) == Some(&'.') {
+                i += 1;
+            }
+        }
+        Ok(res)
+    }
+
+    /// Replaces `:name` placeholders outside of literals, `--` and `/* */` comments with `?`.
+    /// Returns the query and bind values in placeholders order.
+    pub fn bind(
+        &mut self,
+        query: &str,
+        prompt: &mut impl FnMut(&str) -> Result<String, Error>,
+    ) -> Result<(String, Vec<(String, String)>), Error> {
+        let chars: Vec<char> = query.chars().collect();
+        let mut res = String::with_capacity(query.len());
+        let mut binds: Vec<(String, String)> = Vec::new();
+        let mut quote: Option<char> = None;
+        let mut line_comment = false;
+        let mut block_comment = false;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let ch = chars[i];
+            if line_comment {
+                line_comment = ch != '\n';
+            } else if block_comment {
+                if ch == '*' && chars.get(i + 1) == Some(&'/') {
+                    block_comment = false;
+                    res.push_str("*/");
+                    i += 2;
+                    continue;
+                }
+            } else if let Some(q) = quote {
+                if ch == q {
+                    quote = None;
+                }
+            } else if ch == '\'' || ch == '"' {
+                quote = Some(ch);
+            } else if ch == '-' && chars.get(i + 1) == Some(&'-') {
+                line_comment = true;
+            } else if ch == '/' && chars.get(i + 1) == Some(&'*') {
+                block_comment = true;
+                res.push_str("/*");
+                i += 2;
+                continue;
+            } else if ch == ':'
+                && (i == 0 || chars[i - 1] != ':')
+                && chars
+                    .get(i + 1)
+                    .is_some_and(|c| c.is_alphabetic() || *c == '_')
+            {
+                let name_end = name_end(&chars, i + 1);
+                let name: String = chars[i + 1..name_end].iter().collect();
+                let value = self.get_or_prompt(&name, true, prompt)?;
+                binds.push((name, value));
+                res.push('?');
+                i = name_end;
+                continue;
+            }
+            res.push(ch);
+            i += 1;
+        }
+        Ok((res, binds))
+    }
+}
+
+fn name_end(chars: &[char], start: usize) -> usize {
+    let mut end = start;
+    while end < chars.len() && (chars[end].is_alphanumeric() || chars[end] == '_') {
+        end += 1;
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn no_prompt(name: &str) -> Result<String, Error> {
+        Err(anyhow::anyhow!("prompted {}", name))
+    }
+
+    #[test]
+    fn substitutes_defined_vars() {
+        let mut vars = Vars::new(&[("Schema".to_string(), "dw".to_string())]);
+        let query = vars
+            .substitute("select * from &schema..t, &SCHEMA.x", &mut no_prompt)
+            .unwrap();
+        assert_eq!(query, "select * from dw.t, dwx");
+    }
+
+    #[test]
+    fn keeps_prompted_value_of_double_ampersand() {
+        let mut vars = Vars::default();
+        let mut prompts = 0;
+        let mut prompt = |_: &str| {
+            prompts += 1;
+            Ok("v".to_string())
+        };
+        let query = vars.substitute("&&a &a &b &b", &mut prompt).unwrap();
+        assert_eq!(query, "v v v v");
+        // `&a` is kept after `&&a`, `&b` is prompted each time
+        assert_eq!(prompts, 3);
+    }
+
+    #[test]
+    fn keeps_ampersand_without_name() {
+        let mut vars = Vars::default();
+        let query = vars.substitute("select 'a & b'", &mut no_prompt).unwrap();
+        assert_eq!(query, "select 'a & b'");
+    }
+
+    #[test]
+    fn binds_placeholders() {
+        let mut vars = Vars::new(&[
+            ("id".to_string(), "7".to_string()),
+            ("name".to_string(), "x".to_string()),
+        ]);
+        let (query, binds) = vars
+            .bind("select :id, :name, :id from t", &mut no_prompt)
+            .unwrap();
+        assert_eq!(query, "select ?, ?, ? from t");
+        let names: Vec<&str> = binds.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, ["id", "name", "id"]);
+        assert_eq!(binds[0].1, "7");
+    }
+
+    #[test]
+    fn does_not_bind_in_literals_comments_and_casts() {
+        let mut vars = Vars::default();
+        let query = "select ':a', \":b\", x::int -- :c\n/* :d\n :e */ from t";
+        let (bound, binds) = vars.bind(query, &mut no_prompt).unwrap();
+        assert_eq!(bound, query);
+        assert!(binds.is_empty());
+    }
+
+    #[test]
+    fn binds_after_block_comment() {
+        let mut vars = Vars::new(&[("a".to_string(), "1".to_string())]);
+        let (bound, binds) = vars.bind("/* :x */ select :a", &mut no_prompt).unwrap();
+        assert_eq!(bound, "/* :x */ select ?");
+        assert_eq!(binds.len(), 1);
+    }
+}
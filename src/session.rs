@@ -0,0 +1,936 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Error};
+use chrono::{Duration, Local};
+
+use crate::common::args::split_list;
+use crate::common::error::{self, ClientError};
+use crate::common::{
+    eng::{ColDesc, Row},
+    Args, Catalog, ConnectionFn, Engines, Settings,
+};
+use crate::control::Question;
+use crate::engines::impala_web::{self, ImpalaWeb};
+use crate::guard::{self, CostGuard, StatementKind};
+use crate::pattern::Pattern;
+use crate::printing::{self, print_message, to_print_buffer, CellLines};
+use crate::schema_cache::SchemaCache;
+use crate::script::{self, Directive, Location, ScriptItem, SetOption, Vars};
+use crate::stats;
+use crate::view::{Filter, FilterOp, View};
+
+const PRINT_LOAD: &str = "(...)";
+const FETCHED_ALL_ROWS: &str = "Fetched all rows.";
+
+fn protocol(expected: &str) -> Error {
+    ClientError::Protocol(format!("Expected {}", expected)).into()
+}
+
+fn format_duration(duration: Duration) -> String {
+    let hours = duration.num_hours();
+    let minutes = duration.num_minutes() % 60;
+    let seconds = duration.num_seconds() % 60;
+    let milliseconds = duration.num_milliseconds() % 1000;
+
+    format!(
+        "{:02}:{:02}:{:02}:{:03}",
+        hours, minutes, seconds, milliseconds
+    )
+}
+
+/// State of a script run: messages and fetched rows of the last query.
+/// The cursor is kept by the caller, as it borrows the connection.
+pub struct Session {
+    pub settings: Settings,
+    pub vars: Vars,
+    query_dir: PathBuf,
+    start_msg: Vec<String>,
+    end_msg: Vec<String>,
+    columns_description: Vec<ColDesc>,
+    /// Fetched rows, `None` for NULL
+    result_buffer: Vec<Row>,
+    /// Sort and filters of the fetched rows set by the control commands
+    view: View,
+    is_fetched_all_rows: bool,
+    /// Query text, bound query and its params of the last query, for `explain`
+    last_query: Option<(String, String, Vec<String>)>,
+    /// Tag of the last executed query, to find it on the Impala coordinator
+    last_query_tag: Option<String>,
+    /// Changes were made since the last commit in manual commit mode
+    transaction_open: bool,
+    /// Exit code of the failed statement
+    pub exit_code: i32,
+}
+
+impl Session {
+    pub fn new(settings: Settings, vars: Vars, query_dir: PathBuf, connected_msg: String) -> Self {
+        Session {
+            settings,
+            vars,
+            query_dir,
+            start_msg: vec![connected_msg],
+            end_msg: Vec::new(),
+            columns_description: Vec::new(),
+            result_buffer: Vec::new(),
+            view: View::default(),
+            is_fetched_all_rows: false,
+            last_query: None,
+            last_query_tag: None,
+            transaction_open: false,
+            exit_code: 1,
+        }
+    }
+
+    /// Runs what is requested by the command line: the script, schema browser or dump.
+    pub fn start<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        args: &Args,
+        prompt: &mut impl FnMut(&Question) -> Result<String, Error>,
+    ) -> Result<bool, Error> {
+        if !self.settings.autocommit {
+            self.set_autocommit(client, false)?;
+        }
+        if let Some(what) = &args.browse {
+            self.browse(client, cursor, what)
+        } else if let Some(schemas) = &args.dump_schema {
+            self.dump_schema(client, schemas)
+        } else {
+            self.run_script(
+                client,
+                cursor,
+                &args.get_query()?,
+                Path::new(&args.query_file_name),
+                prompt,
+            )
+        }
+    }
+
+    /// Executes the script read from `script_file` printing result of each query.
+    /// Returns `false` if a query failed, the error is printed with the messages.
+    pub fn run_script<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        raw_query: &str,
+        script_file: &Path,
+        prompt: &mut impl FnMut(&Question) -> Result<String, Error>,
+    ) -> Result<bool, Error> {
+        let items = script::load_script(raw_query, script_file, &self.query_dir)?;
+        let queries_cnt = items
+            .iter()
+            .filter(|(_, i)| matches!(i, ScriptItem::Query(_)))
+            .count();
+
+        let mut statement_idx = 0;
+        for (location, item) in items {
+            let query = match item {
+                ScriptItem::Query(query) => {
+                    statement_idx += 1;
+                    query
+                }
+                ScriptItem::Directive(d) => {
+                    self.run_directive(client, d)?;
+                    continue;
+                }
+            };
+            if query.is_empty() {
+                return Err(ClientError::Usage("Empty query".to_string()).into());
+            }
+
+            let mut prompt_value = |name: &str| prompt(&Question::Value(name));
+            let query = self.vars.substitute(&query, &mut prompt_value)?;
+            let (bound_query, binds) = self.vars.bind(&query, &mut prompt_value)?;
+            let start_msg_len = self.start_msg.len();
+            self.start_msg.push(query.clone());
+            if !binds.is_empty() {
+                self.start_msg.push(format!(
+                    "Binds: {}",
+                    binds
+                        .iter()
+                        .map(|(name, value)| format!(":{} = '{}'", name, value))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ));
+            }
+            let params: Vec<String> = binds.into_iter().map(|(_, value)| value).collect();
+            self.last_query = Some((query.clone(), bound_query.clone(), params.clone()));
+
+            if let Some(level) = self.settings.explain {
+                if !self.print_plan(client, &bound_query, &params, Some(level)) {
+                    return Ok(false);
+                }
+                self.start_msg.truncate(start_msg_len);
+                continue;
+            }
+            if self.settings.read_only && guard::classify(&query) == StatementKind::Write {
+                self.start_msg.push(format!(
+                    "Blocked in read-only mode: {} statement",
+                    guard::keyword(&query).to_uppercase()
+                ));
+                print_message(&self.start_msg, None, &self.end_msg);
+                return Ok(false);
+            }
+            if let Some(refused) = self.check_destructive(&query, prompt)? {
+                self.start_msg.push(refused);
+                print_message(&self.start_msg, None, &self.end_msg);
+                return Ok(false);
+            }
+            if let Some(refused) = self.check_cost(client, &bound_query, &params, prompt)? {
+                self.start_msg.push(refused);
+                print_message(&self.start_msg, None, &self.end_msg);
+                return Ok(false);
+            }
+
+            self.last_query_tag = self.impala_web(client).ok().map(|_| impala_web::new_tag());
+            let exec_query = match &self.last_query_tag {
+                Some(tag) => impala_web::tag_query(&bound_query, tag),
+                None => bound_query,
+            };
+
+            let start_time = Local::now();
+            match client.execute(
+                &exec_query,
+                &params,
+                self.settings.fetch_num,
+                self.settings.timeout,
+            ) {
+                Ok((col_desc, c)) => {
+                    *cursor = c;
+                    self.columns_description = col_desc;
+                }
+                Err(e) => {
+                    self.print_error_at(&e, &location, statement_idx, queries_cnt);
+                    return Ok(false);
+                }
+            };
+            if !self.settings.autocommit {
+                match guard::keyword(&query).as_str() {
+                    "commit" | "rollback" => self.transaction_open = false,
+                    _ if guard::classify(&query) == StatementKind::Write => {
+                        self.transaction_open = true
+                    }
+                    _ => {}
+                }
+            }
+            if let Err(e) = self.fetch_first(client, cursor) {
+                self.print_error_at(&e, &location, statement_idx, queries_cnt);
+                return Ok(false);
+            }
+
+            let duration = Local::now() - start_time;
+            let end_msg_len = self.end_msg.len();
+            if self.settings.timing {
+                self.end_msg
+                    .push(format!("Elapsed {} s", format_duration(duration)));
+            }
+
+            //------ process data ----------------
+            //------ print result ----------------
+            // no grid for statements without a result set
+            if cursor.is_some() {
+                self.print_result();
+            } else {
+                print_message(&self.start_msg, None, &self.end_msg);
+            }
+            if self.settings.summary {
+                self.print_summary(client);
+            }
+            if queries_cnt > 1 {
+                self.start_msg.truncate(start_msg_len);
+                self.end_msg.truncate(end_msg_len);
+            }
+        }
+
+        if self.settings.explain.is_none() {
+            self.print_fetch_state();
+        }
+        Ok(true)
+    }
+
+    /// Asks to confirm DROP, TRUNCATE and DELETE or UPDATE without WHERE, returns why the
+    /// statement is not run.
+    fn check_destructive(
+        &self,
+        query: &str,
+        prompt: &mut impl FnMut(&Question) -> Result<String, Error>,
+    ) -> Result<Option<String>, Error> {
+        if !self.settings.confirm_destructive {
+            return Ok(None);
+        }
+        let Some(action) = guard::destructive(query) else {
+            return Ok(None);
+        };
+        // the prompt is a single line
+        let statement = query.split_whitespace().collect::<Vec<&str>>().join(" ");
+        let answer = prompt(&Question::Confirm(&format!(
+            "{}: {}. Run the statement?",
+            action, statement
+        )))?;
+        if Question::is_yes(&answer) {
+            Ok(None)
+        } else {
+            Ok(Some(format!("Cancelled: {}", action)))
+        }
+    }
+
+    /// Explains the query when the cost guard is enabled, returns why it is not run if the
+    /// estimate exceeds the limits and the user did not confirm it.
+    fn check_cost<C: ConnectionFn>(
+        &self,
+        client: &C,
+        query: &str,
+        params: &[String],
+        prompt: &mut impl FnMut(&Question) -> Result<String, Error>,
+    ) -> Result<Option<String>, Error> {
+        let Some(guard) = &self.settings.cost_guard else {
+            return Ok(None);
+        };
+        if !CostGuard::applies(query) {
+            return Ok(None);
+        }
+        // the query fails the same way when it is executed
+        let Ok(plan) = client.explain(query, params, None) else {
+            return Ok(None);
+        };
+        let Some(reason) = guard.exceeded(&plan.scan_estimate(client.engine())) else {
+            return Ok(None);
+        };
+        if !guard.confirm {
+            return Ok(Some(format!("Refused by the cost guard: {}", reason)));
+        }
+        let answer = prompt(&Question::Confirm(&format!("{}. Run the query?", reason)))?;
+        if Question::is_yes(&answer) {
+            Ok(None)
+        } else {
+            Ok(Some(format!("Cancelled: {}", reason)))
+        }
+    }
+
+    /// Prints plan of the last query.
+    pub fn explain<C: ConnectionFn>(
+        &mut self,
+        client: &C,
+        level: Option<u8>,
+    ) -> Result<bool, Error> {
+        let Some((query, bound_query, params)) = self.last_query.clone() else {
+            printing::print_line("Nothing to explain");
+            return Ok(false);
+        };
+        self.start_msg.truncate(1);
+        self.end_msg.clear();
+        self.start_msg.push(query);
+        Ok(self.print_plan(client, &bound_query, &params, level))
+    }
+
+    /// Prints the messages with the plan, or with the error and returns `false`.
+    fn print_plan<C: ConnectionFn>(
+        &mut self,
+        client: &C,
+        query: &str,
+        params: &[String],
+        level: Option<u8>,
+    ) -> bool {
+        match client.explain(query, params, level) {
+            Ok(plan) => {
+                for msg in &self.start_msg {
+                    printing::print_line(&format!("{}\n", msg));
+                }
+                for line in plan.render() {
+                    printing::print_line(&line);
+                }
+                true
+            }
+            Err(e) => {
+                self.print_error(&e);
+                false
+            }
+        }
+    }
+
+    /// Prints the schema browser result as of a query, `false` if it failed.
+    pub fn browse<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        what: &Catalog,
+    ) -> Result<bool, Error> {
+        self.start_msg.truncate(1);
+        self.end_msg.clear();
+        self.start_msg.push(what.to_string());
+        match client.browse(what, self.settings.fetch_num) {
+            Ok((col_desc, c)) => {
+                *cursor = c;
+                self.columns_description = col_desc;
+            }
+            Err(e) => {
+                self.print_error(&e);
+                return Ok(false);
+            }
+        };
+        if let Err(e) = self.fetch_first(client, cursor) {
+            self.print_error(&e);
+            return Ok(false);
+        }
+
+        self.print_result();
+        self.print_fetch_state();
+        Ok(true)
+    }
+
+    /// Runs the control command, `None` if it is not a session command.
+    pub fn command<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        cmd: &str,
+        arg: Option<&str>,
+    ) -> Result<Option<bool>, Error> {
+        if cmd == "load" {
+            let fetch_num = arg
+                .unwrap_or_default()
+                .parse::<i32>()
+                .map_err(|_| protocol("load==N"))?;
+            self.load(client, cursor, fetch_num)?;
+            return Ok(Some(true));
+        }
+        if cmd == "explain" {
+            let level = arg
+                .map(|l| l.parse::<u8>())
+                .transpose()
+                .map_err(|_| protocol("explain[==level]"))?;
+            return self.explain(client, level).map(Some);
+        }
+        if cmd == "commit" || cmd == "rollback" {
+            self.end_transaction(client, cmd == "commit")?;
+            return Ok(Some(true));
+        }
+        if cmd == "summary" {
+            return Ok(Some(self.print_summary(client)));
+        }
+        if cmd == "profile" {
+            let path = arg.ok_or_else(|| protocol("profile==path"))?;
+            return Ok(Some(self.save_profile(client, path)));
+        }
+        if cmd == "dump-cell" {
+            let expected = "dump-cell==row==column==path";
+            let mut parts = arg.unwrap_or_default().splitn(3, "==");
+            let (Some(row), Some(column), Some(path)) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(protocol(expected));
+            };
+            let row = row
+                .parse::<usize>()
+                .ok()
+                .filter(|row| *row > 0)
+                .ok_or_else(|| protocol(expected))?;
+            return Ok(Some(self.dump_cell(client, row, column, path)));
+        }
+        if cmd == "sort" {
+            let expected = "sort==column[==asc|desc]";
+            let mut parts = arg.ok_or_else(|| protocol(expected))?.splitn(2, "==");
+            let column = parts.next().unwrap_or_default();
+            let descending = match parts.next().map(str::to_lowercase).as_deref() {
+                None | Some("asc") => false,
+                Some("desc") => true,
+                Some(_) => return Err(protocol(expected)),
+            };
+            return self
+                .change_view(column, |view, col| view.sort(col, descending))
+                .map(Some);
+        }
+        if cmd == "filter" {
+            let expected = "filter==column==op[==value]";
+            let mut parts = arg.ok_or_else(|| protocol(expected))?.splitn(3, "==");
+            let column = parts.next().unwrap_or_default();
+            let op = parts
+                .next()
+                .and_then(FilterOp::parse)
+                .ok_or_else(|| protocol(expected))?;
+            let value = parts.next();
+            if op.has_value() != value.is_some() {
+                return Err(protocol(expected));
+            }
+            let value = value.unwrap_or_default().to_string();
+            return self
+                .change_view(column, |view, col| view.filter(Filter { col, op, value }))
+                .map(Some);
+        }
+        if cmd == "find" {
+            let expected = "find==text|/regex/[==all,fetch]";
+            let mut parts = arg.ok_or_else(|| protocol(expected))?.splitn(2, "==");
+            let pattern = parts.next().unwrap_or_default();
+            let (mut all, mut fetch) = (false, false);
+            for option in parts.next().map(split_list).unwrap_or_default() {
+                match option.as_str() {
+                    "all" => all = true,
+                    "fetch" => fetch = true,
+                    _ => return Err(protocol(expected)),
+                }
+            }
+            return self.find(client, cursor, pattern, all, fetch).map(Some);
+        }
+        if cmd == "stats" {
+            return Ok(Some(self.print_stats()));
+        }
+        if cmd == "reset" {
+            self.view = View::default();
+            self.print_result();
+            return Ok(Some(true));
+        }
+        if cmd == "dump-schema" {
+            let schemas = arg.map(split_list).unwrap_or_default();
+            return self.dump_schema(client, &schemas).map(Some);
+        }
+        match Catalog::parse(cmd, arg)? {
+            Some(what) => self.browse(client, cursor, &what).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Refreshes the schema cache file, all schemas if `schemas` is empty.
+    pub fn dump_schema<C: ConnectionFn>(
+        &mut self,
+        client: &C,
+        schemas: &[String],
+    ) -> Result<bool, Error> {
+        let path = &self.settings.schema_cache;
+        let mut cache = SchemaCache::load(path)?;
+        if let Err(e) = cache.refresh(client, schemas) {
+            printing::print_line(&format!("Schema dump failed: {}", e));
+            return Ok(false);
+        }
+        cache.save(path)?;
+        printing::print_line(&format!(
+            "Schema cache {} updated: {} schemas, {} tables",
+            path.display(),
+            cache.schemas.len(),
+            cache.tables_count()
+        ));
+        Ok(true)
+    }
+
+    /// Web UI of the coordinator, only Impala queries have a summary.
+    fn impala_web<C: ConnectionFn>(&self, client: &C) -> Result<ImpalaWeb, Error> {
+        if !matches!(client.engine(), Engines::Impala) {
+            return Err(anyhow!("Query summary is supported for Impala only"));
+        }
+        self.settings
+            .impala_web_url
+            .as_deref()
+            .map(ImpalaWeb::new)
+            .ok_or_else(|| anyhow!("Set impala_web_url in the profile to get the query summary"))
+    }
+
+    fn last_query_id(&self, web: &ImpalaWeb) -> Result<String, Error> {
+        let tag = self
+            .last_query_tag
+            .as_deref()
+            .ok_or_else(|| anyhow!("No query was run"))?;
+        web.query_id(tag)
+    }
+
+    /// Prints time, rows and peak memory per operator of the last query. If the summary is
+    /// not available the reason is printed, the query result is not affected.
+    fn print_summary<C: ConnectionFn>(&self, client: &C) -> bool {
+        let summary = self.impala_web(client).and_then(|web| {
+            let id = self.last_query_id(&web)?;
+            Ok((web.summary(&id)?, id))
+        });
+        match summary {
+            Ok((summary, id)) => {
+                printing::print_line(&format!("Summary of query {}", id));
+                for line in impala_web::summary_lines(&summary) {
+                    printing::print_line(&line);
+                }
+                true
+            }
+            Err(e) => {
+                printing::print_line(&format!("Summary is not available: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Saves the full profile of the last query, the path is relative to the script.
+    fn save_profile<C: ConnectionFn>(&self, client: &C, path: &str) -> bool {
+        let path = self.query_dir.join(path);
+        let saved = self.impala_web(client).and_then(|web| {
+            let id = self.last_query_id(&web)?;
+            fs::write(&path, web.profile(&id)?)?;
+            Ok(id)
+        });
+        match saved {
+            Ok(id) => {
+                printing::print_line(&format!(
+                    "Profile of query {} saved to {}",
+                    id,
+                    path.display()
+                ));
+                true
+            }
+            Err(e) => {
+                printing::print_line(&format!("Profile is not saved: {}", e));
+                false
+            }
+        }
+    }
+
+    /// Saves the whole value of the cell of the last query result, the query is run again.
+    /// `column` is the number or the name of the column, the path is relative to the script.
+    fn dump_cell<C: ConnectionFn>(&self, client: &C, row: usize, column: &str, path: &str) -> bool {
+        let path = self.query_dir.join(path);
+        let saved = self
+            .last_query
+            .as_ref()
+            .ok_or_else(|| anyhow!("No query was run"))
+            .and_then(|(query, bound_query, params)| {
+                if guard::classify(query) == StatementKind::Write {
+                    return Err(anyhow!("The last statement is not a query"));
+                }
+                let col = self.column_number(column)?;
+                let mut file = fs::File::create(&path)?;
+                client.dump_cell(bound_query, params, row, col, &mut file)
+            });
+        match saved {
+            Ok(size) => {
+                printing::print_line(&format!(
+                    "Cell {}:{} saved to {} ({} bytes)",
+                    row,
+                    column,
+                    path.display(),
+                    size
+                ));
+                true
+            }
+            Err(e) => {
+                printing::print_line(&format!("Cell is not saved: {:#}", e));
+                false
+            }
+        }
+    }
+
+    /// 1-based number of the result column given by its number or name.
+    fn column_number(&self, column: &str) -> Result<u16, Error> {
+        let cols = &self.columns_description;
+        let idx = match column.parse::<usize>() {
+            Ok(n) if (1..=cols.len()).contains(&n) => Some(n - 1),
+            Ok(_) => None,
+            Err(_) => cols
+                .iter()
+                .position(|c| c.col_name.eq_ignore_ascii_case(column)),
+        };
+        idx.map(|idx| idx as u16 + 1)
+            .ok_or_else(|| anyhow!("No column {} in the result", column))
+    }
+
+    /// Changes the view by the column given by its number or name and prints the result,
+    /// `false` if there is no such column.
+    fn change_view(
+        &mut self,
+        column: &str,
+        change: impl FnOnce(&mut View, usize),
+    ) -> Result<bool, Error> {
+        match self.column_number(column) {
+            Ok(col) => {
+                change(&mut self.view, col as usize - 1);
+                self.print_result();
+                Ok(true)
+            }
+            Err(e) => {
+                printing::print_line(&e.to_string());
+                Ok(false)
+            }
+        }
+    }
+
+    /// Prints the messages with the grid of the fetched rows as the view shows them.
+    fn print_result(&self) {
+        let mut msg = Vec::new();
+        if !self.view.is_empty() {
+            msg.push(format!(
+                "Shown {} of {} fetched rows ({})",
+                self.view
+                    .apply(&self.columns_description, &self.result_buffer)
+                    .len(),
+                self.result_buffer.len(),
+                self.view.describe(&self.columns_description)
+            ));
+        }
+        self.print_grid(self.print_buffer(), msg);
+    }
+
+    /// Prints the messages with the grid, followed by the column statistics when they are on.
+    /// `msg` goes ahead of the end messages.
+    fn print_grid(&self, print_buffer: Vec<Vec<CellLines>>, msg: Vec<String>) {
+        let mut end_msg = Vec::new();
+        if self.settings.stats {
+            end_msg.push(self.stats());
+        }
+        end_msg.extend(msg);
+        end_msg.extend(self.end_msg.iter().cloned());
+        print_message(&self.start_msg, Some(print_buffer), &end_msg);
+    }
+
+    /// Statistics of the columns over the rows of the view.
+    fn stats(&self) -> String {
+        let rows = self
+            .view
+            .apply(&self.columns_description, &self.result_buffer);
+        format!(
+            "\nStatistics of {} rows\n{}",
+            rows.len(),
+            stats::render(
+                &self.columns_description,
+                &rows,
+                self.settings.temporal.as_ref()
+            )
+            .join("\n")
+        )
+    }
+
+    /// Prints the column statistics of the last result, `false` if there is none.
+    fn print_stats(&self) -> bool {
+        if self.columns_description.is_empty() {
+            printing::print_line("No result to summarize");
+            return false;
+        }
+        printing::print_line(self.stats().trim_start());
+        true
+    }
+
+    /// Prints the rows of the view with values matching the pattern, or all rows with `all`,
+    /// followed by the positions of the matches in the grid as `match row:column`. Matching
+    /// values are marked as `»value«`. With `fetch` next rows are fetched until a match is found.
+    fn find<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        pattern: &str,
+        all: bool,
+        fetch: bool,
+    ) -> Result<bool, Error> {
+        let pattern = match Pattern::parse(pattern) {
+            Ok(pattern) => pattern,
+            Err(e) => {
+                printing::print_line(&e.to_string());
+                return Ok(false);
+            }
+        };
+        // values as shown without the markers, NULL does not match
+        let mut rows = self.view_rows();
+        while fetch
+            && !self.is_fetched_all_rows
+            && !rows.iter().flatten().flatten().any(|v| pattern.is_match(v))
+        {
+            self.fetch_more(client, cursor, self.settings.fetch_num)?;
+            rows = self.view_rows();
+        }
+
+        let shown = self.settings.display.render_rows(&rows);
+        let found = pattern.mark(&rows, shown, all);
+        let mut msg = vec![format!(
+            "Found {} matches in {} of {} rows",
+            found.positions.len(),
+            found.matched_rows,
+            rows.len()
+        )];
+        msg.extend(found.positions);
+        self.print_grid(to_print_buffer(&self.columns_description, &found.grid), msg);
+        Ok(true)
+    }
+
+    /// Prints the messages with the error of the statement, its class is kept for the exit code.
+    fn print_error(&mut self, e: &Error) {
+        self.start_msg.push(format!("{:#}", e));
+        print_message(&self.start_msg, None, &self.end_msg);
+        self.exit_code = error::exit_code(e);
+    }
+
+    /// Same as [`Session::print_error`], followed by the statement lines and the position of
+    /// the error as `file:line:column` for the plugin to jump to. The position reported by
+    /// the engine is relative to the statement, the start of the statement otherwise.
+    fn print_error_at(
+        &mut self,
+        e: &Error,
+        location: &Location,
+        statement_idx: usize,
+        queries_cnt: usize,
+    ) {
+        let msg = format!("{:#}", e);
+        let (line, column) = match script::error_position(&msg) {
+            Some((line, column)) => location.resolve(line, column),
+            None => (location.line, location.column),
+        };
+        self.start_msg.push(msg);
+        self.start_msg.push(format!(
+            "Statement {} of {}, lines {}-{}",
+            statement_idx, queries_cnt, location.line, location.end_line
+        ));
+        self.start_msg
+            .push(format!("{}:{}:{}", location.file.display(), line, column));
+        print_message(&self.start_msg, None, &self.end_msg);
+        self.exit_code = error::exit_code(e);
+    }
+
+    /// Fetches the first rows, there are none if the statement has no result set.
+    fn fetch_first<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+    ) -> Result<(), Error> {
+        let (data, fetched_all_rows) = match cursor.as_mut() {
+            Some(c) => client.fetch(c, self.settings.fetch_num)?,
+            None => (Vec::new(), true),
+        };
+        self.is_fetched_all_rows = fetched_all_rows;
+        self.result_buffer = data;
+        self.view = View::default();
+        Ok(())
+    }
+
+    /// Grid of the fetched rows with NULL and empty values shown by their markers, sorted and
+    /// filtered by the view.
+    fn print_buffer(&self) -> Vec<Vec<CellLines>> {
+        to_print_buffer(&self.columns_description, &self.view_values())
+    }
+
+    /// Shown values of the fetched rows sorted and filtered by the view.
+    fn view_values(&self) -> Vec<Vec<String>> {
+        self.settings.display.render_rows(&self.view_rows())
+    }
+
+    /// Fetched rows sorted and filtered by the view, in the display formats. The rows are kept
+    /// as fetched, so they are sorted and filtered by their values, the formats apply here.
+    fn view_rows(&self) -> Vec<Row> {
+        let mut rows: Vec<Row> = self
+            .view
+            .apply(&self.columns_description, &self.result_buffer)
+            .into_iter()
+            .cloned()
+            .collect();
+        if let Some(temporal) = &self.settings.temporal {
+            temporal.apply(&self.columns_description, &mut rows);
+        }
+        rows
+    }
+
+    fn print_fetch_state(&mut self) {
+        if !self.is_fetched_all_rows {
+            printing::print_line(PRINT_LOAD);
+            self.end_msg.push(PRINT_LOAD.to_string());
+        } else {
+            printing::print_line(FETCHED_ALL_ROWS);
+        }
+    }
+
+    /// Fetches next rows of the last query and prints all fetched rows.
+    pub fn load<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        fetch_num: i32,
+    ) -> Result<(), Error> {
+        printing::print_line(&format!(
+            "is_fetched_all_rows = {}",
+            self.is_fetched_all_rows
+        ));
+        if !self.is_fetched_all_rows {
+            self.fetch_more(client, cursor, fetch_num)?;
+        } else if let Some(last_element) = self.end_msg.last_mut() {
+            *last_element = FETCHED_ALL_ROWS.to_string(); // Update the value of the last element
+        }
+
+        self.print_result();
+        Ok(())
+    }
+
+    /// Fetches next rows of the last query into the buffer.
+    fn fetch_more<'a, C: ConnectionFn>(
+        &mut self,
+        client: &'a C,
+        cursor: &mut Option<C::Cursor<'a>>,
+        fetch_num: i32,
+    ) -> Result<(), Error> {
+        let (mut data, fetched_all_rows) = match cursor.as_mut() {
+            Some(c) => client.fetch(c, fetch_num)?,
+            None => (Vec::new(), true),
+        };
+        self.is_fetched_all_rows = fetched_all_rows;
+        self.result_buffer.append(&mut data);
+
+        if self.is_fetched_all_rows {
+            if let Some(last_element) = self.end_msg.last_mut() {
+                *last_element = FETCHED_ALL_ROWS.to_string(); // Update the value of the last element
+            }
+        }
+        Ok(())
+    }
+
+    fn set_autocommit<C: ConnectionFn>(&mut self, client: &C, on: bool) -> Result<(), Error> {
+        client.set_autocommit(on)?;
+        self.settings.autocommit = on;
+        if on {
+            // the open transaction is committed by the driver
+            self.transaction_open = false;
+        }
+        Ok(())
+    }
+
+    /// Commits or rolls back the open transaction.
+    fn end_transaction<C: ConnectionFn>(&mut self, client: &C, commit: bool) -> Result<(), Error> {
+        if commit {
+            client.commit()?;
+        } else {
+            client.rollback()?;
+        }
+        self.transaction_open = false;
+        printing::print_line(if commit {
+            "Commit complete."
+        } else {
+            "Rollback complete."
+        });
+        Ok(())
+    }
+
+    /// Rolls back uncommitted changes with a warning, and leaves the connection in
+    /// autocommit mode for the next session. If the rollback fails autocommit is left off,
+    /// switching it on would commit the changes, so the connection must not be reused.
+    pub fn end<C: ConnectionFn>(&mut self, client: &C) -> Result<(), Error> {
+        if self.transaction_open {
+            printing::print_line("Warning: session ended with uncommitted changes, rolling back");
+            self.end_transaction(client, false)
+                .context("Rollback of the uncommitted changes failed")?;
+        }
+        if !self.settings.autocommit {
+            self.set_autocommit(client, true)?;
+        }
+        Ok(())
+    }
+
+    fn run_directive<C: ConnectionFn>(
+        &mut self,
+        client: &C,
+        directive: Directive,
+    ) -> Result<(), Error> {
+        match directive {
+            Directive::Define(name, value) => self.vars.define(&name, &value),
+            Directive::Undefine(names) => {
+                for name in names {
+                    self.vars.undefine(&name);
+                }
+            }
+            // includes are resolved by script::load_script
+            Directive::Include(_) => {}
+            Directive::Set(SetOption::Autocommit(on)) => self.set_autocommit(client, on)?,
+            Directive::Set(option) => self.settings.set(option),
+            Directive::Spool(path) => {
+                printing::spool(path.map(|p| self.query_dir.join(p)).as_deref())?
+            }
+            Directive::Prompt(text) => printing::print_line(&text),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+//! Column statistics of the fetched rows, printed under the grid.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+
+use crate::common::eng::{ColDesc, Row};
+use crate::printing;
+use crate::temporal::TemporalFormat;
+use crate::view;
+
+/// Distinct values counted exactly, beyond that they are estimated.
+const EXACT_DISTINCT: usize = 10_000;
+/// Bits of the hash selecting a HyperLogLog register, error about 1.6%.
+const HLL_BITS: u32 = 12;
+/// Characters of min and max values shown.
+const VALUE_WIDTH: usize = 30;
+
+/// Estimate of the number of distinct values.
+struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; 1 << HLL_BITS],
+        }
+    }
+
+    fn insert(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let idx = (hash >> (64 - HLL_BITS)) as usize;
+        // the marker bit limits the rank when the rest of the hash is zero
+        let rest = (hash << HLL_BITS) | (1 << (HLL_BITS - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[idx] = self.registers[idx].max(rank);
+    }
+
+    fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            // linear counting is closer for small numbers
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+enum Distinct<'a> {
+    Exact(HashSet<&'a str>),
+    Approx(HyperLogLog),
+}
+
+impl<'a> Distinct<'a> {
+    fn insert(&mut self, value: &'a str) {
+        match self {
+            Distinct::Exact(values) => {
+                values.insert(value);
+                if values.len() > EXACT_DISTINCT {
+                    let mut hll = HyperLogLog::new();
+                    values.iter().for_each(|v| hll.insert(v));
+                    *self = Distinct::Approx(hll);
+                }
+            }
+            Distinct::Approx(hll) => hll.insert(value),
+        }
+    }
+
+    /// Estimates are marked with `~`.
+    fn render(&self) -> String {
+        match self {
+            Distinct::Exact(values) => values.len().to_string(),
+            Distinct::Approx(hll) => format!("~{}", hll.estimate()),
+        }
+    }
+}
+
+/// Sum, mean and variance of the numeric values, Welford's method.
+#[derive(Default)]
+struct Numbers {
+    count: usize,
+    sum: f64,
+    mean: f64,
+    m2: f64,
+}
+
+impl Numbers {
+    fn insert(&mut self, x: f64) {
+        self.count += 1;
+        self.sum += x;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Sample standard deviation.
+    fn stddev(&self) -> Option<f64> {
+        (self.count > 1).then(|| (self.m2 / (self.count - 1) as f64).sqrt())
+    }
+}
+
+struct ColumnStats<'a> {
+    column: &'a ColDesc,
+    /// Values which are not NULL.
+    count: usize,
+    nulls: usize,
+    distinct: Distinct<'a>,
+    min: Option<&'a str>,
+    max: Option<&'a str>,
+    /// Only for numeric columns.
+    numbers: Option<Numbers>,
+}
+
+impl<'a> ColumnStats<'a> {
+    fn new(column: &'a ColDesc) -> Self {
+        ColumnStats {
+            column,
+            count: 0,
+            nulls: 0,
+            distinct: Distinct::Exact(HashSet::new()),
+            min: None,
+            max: None,
+            numbers: column.is_numeric().then(Numbers::default),
+        }
+    }
+
+    fn insert(&mut self, value: Option<&'a str>) {
+        let Some(value) = value else {
+            self.nulls += 1;
+            return;
+        };
+        self.count += 1;
+        self.distinct.insert(value);
+        // temporal values by time, they are not formatted yet
+        let less = |a: &str, b: &str| view::compare(self.column, Some(a), Some(b)).is_lt();
+        if self.min.is_none_or(|min| less(value, min)) {
+            self.min = Some(value);
+        }
+        if self.max.is_none_or(|max| less(max, value)) {
+            self.max = Some(value);
+        }
+        if let (Some(numbers), Ok(x)) = (&mut self.numbers, value.trim().parse::<f64>()) {
+            numbers.insert(x);
+        }
+    }
+
+    fn render(&self, temporal: Option<&TemporalFormat>) -> Vec<String> {
+        let numbers = self.numbers.as_ref().filter(|n| n.count > 0);
+        let shown = |value: &str| {
+            let formatted = temporal.and_then(|t| t.format(self.column, value));
+            shorten(formatted.as_deref().unwrap_or(value))
+        };
+        vec![
+            self.column.col_name.clone(),
+            self.count.to_string(),
+            self.nulls.to_string(),
+            self.distinct.render(),
+            self.min.map(shown).unwrap_or_default(),
+            self.max.map(shown).unwrap_or_default(),
+            numbers.map(|n| number(n.sum)).unwrap_or_default(),
+            numbers.map(|n| number(n.mean)).unwrap_or_default(),
+            numbers
+                .and_then(Numbers::stddev)
+                .map(number)
+                .unwrap_or_default(),
+        ]
+    }
+}
+
+/// Lines of the aligned table with the statistics of each column, the rows are as fetched and
+/// min and max are shown in the display formats.
+pub fn render(
+    columns: &[ColDesc],
+    rows: &[&Row],
+    temporal: Option<&TemporalFormat>,
+) -> Vec<String> {
+    let mut stats: Vec<ColumnStats> = columns.iter().map(ColumnStats::new).collect();
+    for row in rows {
+        for (col, value) in stats.iter_mut().zip(row.iter()) {
+            col.insert(value.as_deref());
+        }
+    }
+    let header = [
+        "column", "count", "nulls", "distinct", "min", "max", "sum", "avg", "stddev",
+    ];
+    let mut table = vec![header.iter().map(|h| h.to_string()).collect()];
+    table.extend(stats.iter().map(|col| col.render(temporal)));
+    printing::align(&table)
+}
+
+fn shorten(value: &str) -> String {
+    match value.char_indices().nth(VALUE_WIDTH) {
+        Some((idx, _)) => format!("{}…", &value[..idx]),
+        None => value.to_string(),
+    }
+}
+
+/// Whole numbers without the fraction, others with up to 4 digits of it.
+fn number(x: f64) -> String {
+    if x.fract() == 0.0 && x.abs() < 1e15 {
+        format!("{:.0}", x)
+    } else {
+        let s = format!("{:.4}", x);
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_distinct_beyond_the_exact_limit() {
+        let values: Vec<String> = (0..2 * EXACT_DISTINCT).map(|i| format!("v{}", i)).collect();
+        let mut distinct = Distinct::Exact(HashSet::new());
+        for value in &values[..EXACT_DISTINCT] {
+            distinct.insert(value);
+            // repeated values are counted once
+            distinct.insert(&values[0]);
+        }
+        assert_eq!(distinct.render(), EXACT_DISTINCT.to_string());
+
+        distinct.insert(&values[EXACT_DISTINCT]);
+        assert!(matches!(distinct, Distinct::Approx(_)));
+        for value in &values[EXACT_DISTINCT..] {
+            distinct.insert(value);
+        }
+        let shown = distinct.render();
+        let estimate: f64 = shown.strip_prefix('~').unwrap().parse().unwrap();
+        let error = (estimate - values.len() as f64).abs() / values.len() as f64;
+        assert!(error < 0.05, "{}", shown);
+    }
+
+    #[test]
+    fn sums_and_deviates() {
+        let mut numbers = Numbers::default();
+        assert_eq!(numbers.stddev(), None);
+        for x in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            numbers.insert(x);
+        }
+        assert_eq!(numbers.sum, 40.0);
+        assert_eq!(numbers.mean, 5.0);
+        assert_eq!(
+            number(numbers.stddev().unwrap()),
+            number((32.0f64 / 7.0).sqrt())
+        );
+        assert_eq!(number(numbers.stddev().unwrap()), "2.1381");
+
+        // large offsets do not lose the variance
+        let mut numbers = Numbers::default();
+        for x in [1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0] {
+            numbers.insert(x);
+        }
+        assert_eq!(numbers.mean, 1e9 + 10.0);
+        assert_eq!(numbers.stddev(), Some(30f64.sqrt()));
+    }
+
+    #[test]
+    fn counts_values_without_nulls() {
+        let column = ColDesc::new(0, "n".to_string(), "Integer".to_string());
+        let mut stats = ColumnStats::new(&column);
+        for value in [Some("10"), None, Some("9"), Some("x"), None] {
+            stats.insert(value);
+        }
+        assert_eq!(
+            stats.render(None),
+            ["n", "3", "2", "3", "9", "x", "19", "9.5", "0.7071"]
+        );
+    }
+
+    #[test]
+    fn orders_temporal_values_by_time() {
+        let column = ColDesc::new(0, "ts".to_string(), "Timestamp".to_string());
+        let mut stats = ColumnStats::new(&column);
+        for value in [
+            "2024-05-01 10:00:00.5",
+            "2024-05-01 12:00:00 +03:00",
+            "2024-05-01 10:00:00",
+        ] {
+            stats.insert(Some(value));
+        }
+        assert_eq!(stats.min, Some("2024-05-01 12:00:00 +03:00"));
+        assert_eq!(stats.max, Some("2024-05-01 10:00:00.5"));
+    }
+}
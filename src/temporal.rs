@@ -0,0 +1,398 @@
+//! Display of date, time and timestamp values: formats and the time zone.
+
+use anyhow::{anyhow, Error};
+use chrono::{
+    DateTime, Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone,
+    Timelike,
+};
+use chrono_tz::Tz;
+
+use crate::common::eng::{ColDesc, Row};
+use crate::common::Profile;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%H:%M:%S%.f";
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// Formats of the temporal columns, profile keys `date_format`, `time_format`,
+/// `timestamp_format`, `fraction_digits`, `time_zone` and `server_time_zone`.
+#[derive(Debug)]
+pub struct TemporalFormat {
+    date: String,
+    time: String,
+    timestamp: String,
+    /// Digits of the seconds fraction, replaces `%.f` of the formats.
+    fraction_digits: Option<usize>,
+    /// Timestamps are converted to the zone.
+    zone: Option<Zone>,
+    /// Zone of the timestamps without offset.
+    server_zone: Zone,
+}
+
+impl TemporalFormat {
+    /// `None` if the profile has none of the keys, values are shown as the driver returns them.
+    pub fn from_profile(profile: &Profile) -> Result<Option<Self>, Error> {
+        let keys = [
+            "date_format",
+            "time_format",
+            "timestamp_format",
+            "fraction_digits",
+            "time_zone",
+            "server_time_zone",
+        ];
+        if keys.iter().all(|key| profile.get(key).is_none()) {
+            return Ok(None);
+        }
+        let zone = |key: &str| {
+            profile
+                .get(key)
+                .map(|name| {
+                    Zone::parse(name).ok_or_else(|| {
+                        anyhow!(
+                            "Profile {}: {} must be utc, local, +HH:MM or a zone like Europe/Berlin",
+                            profile.name,
+                            key
+                        )
+                    })
+                })
+                .transpose()
+        };
+        let fraction_digits = match profile.get_u64("fraction_digits")? {
+            Some(digits) if digits > 9 => {
+                return Err(anyhow!(
+                    "Profile {}: fraction_digits must be 0 to 9",
+                    profile.name
+                ))
+            }
+            digits => digits.map(|d| d as usize),
+        };
+        Ok(Some(TemporalFormat {
+            date: profile
+                .get("date_format")
+                .unwrap_or(DATE_FORMAT)
+                .to_string(),
+            time: profile
+                .get("time_format")
+                .unwrap_or(TIME_FORMAT)
+                .to_string(),
+            timestamp: profile
+                .get("timestamp_format")
+                .unwrap_or(TIMESTAMP_FORMAT)
+                .to_string(),
+            fraction_digits,
+            zone: zone("time_zone")?,
+            server_zone: zone("server_time_zone")?.unwrap_or(Zone::Utc),
+        }))
+    }
+
+    /// Formats the values of the temporal columns, values which are not parsed are kept.
+    pub fn apply(&self, columns: &[ColDesc], rows: &mut [Row]) {
+        for (idx, col) in columns.iter().enumerate() {
+            let Some(format) = Self::formatter(&col.col_type) else {
+                continue;
+            };
+            for row in rows.iter_mut() {
+                if let Some(text) = row
+                    .get(idx)
+                    .and_then(Option::as_deref)
+                    .and_then(|value| format(self, value))
+                {
+                    row[idx] = Some(text.into());
+                }
+            }
+        }
+    }
+
+    /// Shown text of a value of the column, `None` if it is kept as it is.
+    pub fn format(&self, column: &ColDesc, value: &str) -> Option<String> {
+        Self::formatter(&column.col_type).and_then(|format| format(self, value))
+    }
+
+    fn formatter(col_type: &str) -> Option<fn(&Self, &str) -> Option<String>> {
+        match col_type {
+            "Date" => Some(Self::format_date),
+            "Time" => Some(Self::format_time),
+            "Timestamp" => Some(Self::format_timestamp),
+            _ => None,
+        }
+    }
+
+    fn format_date(&self, value: &str) -> Option<String> {
+        let date = NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d").ok()?;
+        Some(date.format(&self.date).to_string())
+    }
+
+    fn format_time(&self, value: &str) -> Option<String> {
+        let time = NaiveTime::parse_from_str(value.trim(), "%H:%M:%S%.f").ok()?;
+        Some(
+            time.format(&self.fraction(&self.time, time.nanosecond()))
+                .to_string(),
+        )
+    }
+
+    fn format_timestamp(&self, value: &str) -> Option<String> {
+        let utc = parse_timestamp(value.trim(), &self.server_zone)?;
+        let local = match &self.zone {
+            Some(zone) => utc + Duration::seconds(zone.offset_at(utc).into()),
+            None => match DateTime::parse_from_str(value.trim(), "%Y-%m-%d %H:%M:%S%.f %:z") {
+                // the offset of the value is kept
+                Ok(value) => value.naive_local(),
+                Err(_) => utc + Duration::seconds(self.server_zone.offset_at(utc).into()),
+            },
+        };
+        let format = self.fraction(&self.timestamp, local.nanosecond());
+        Some(local.format(&format).to_string())
+    }
+
+    /// The format with `%.f` replaced by the fraction with the configured digits.
+    fn fraction(&self, format: &str, nanos: u32) -> String {
+        match self.fraction_digits {
+            None => format.to_string(),
+            Some(0) => format.replace("%.f", ""),
+            Some(digits) => {
+                format.replace("%.f", &format!(".{}", &format!("{:09}", nanos)[..digits]))
+            }
+        }
+    }
+}
+
+/// Point in time of a date, time or timestamp value as the driver returns it, to compare the
+/// values of the column. Timestamps with an offset are taken at UTC, the rest as they are.
+/// `None` for other columns and values which are not parsed.
+pub fn instant(col_type: &str, value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    let date = || NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+    match col_type {
+        "Date" => date()?.and_hms_opt(0, 0, 0),
+        "Time" => NaiveTime::parse_from_str(value, "%H:%M:%S%.f")
+            .ok()
+            .map(|time| NaiveDate::default().and_time(time)),
+        // a date compares as its midnight
+        "Timestamp" => parse_timestamp(value, &Zone::Utc).or_else(|| date()?.and_hms_opt(0, 0, 0)),
+        _ => None,
+    }
+}
+
+/// UTC time of the timestamp, with an offset like `+03:00` or in the server zone.
+fn parse_timestamp(value: &str, server_zone: &Zone) -> Option<NaiveDateTime> {
+    let value = value.replacen('T', " ", 1);
+    if let Ok(value) = DateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S%.f %:z") {
+        return Some(value.naive_utc());
+    }
+    let local = NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    // the offset at the local time, corrected once for the change of the offset
+    let mut utc = local - Duration::seconds(server_zone.offset_at(local).into());
+    utc = local - Duration::seconds(server_zone.offset_at(utc).into());
+    Some(utc)
+}
+
+/// Time zone the timestamps are shown in.
+#[derive(Debug)]
+pub enum Zone {
+    Utc,
+    Local,
+    Fixed(FixedOffset),
+    /// Zone of the tz database.
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "utc" | "z" => return Some(Zone::Utc),
+            "local" => return Some(Zone::Local),
+            _ => {}
+        }
+        if let Some(offset) = parse_offset(name) {
+            return FixedOffset::east_opt(offset).map(Zone::Fixed);
+        }
+        name.parse().ok().map(Zone::Named)
+    }
+
+    /// Seconds east of UTC at the UTC time.
+    pub fn offset_at(&self, utc: NaiveDateTime) -> i32 {
+        match self {
+            Zone::Utc => 0,
+            Zone::Local => Local.offset_from_utc_datetime(&utc).fix().local_minus_utc(),
+            Zone::Fixed(offset) => offset.local_minus_utc(),
+            Zone::Named(tz) => tz.offset_from_utc_datetime(&utc).fix().local_minus_utc(),
+        }
+    }
+}
+
+/// `+03:00`, `-0530` or `+3` as seconds east of UTC.
+fn parse_offset(text: &str) -> Option<i32> {
+    let (sign, rest) = match text.as_bytes().first()? {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h, m),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    (hours <= 14 && minutes < 60).then_some(sign * (hours * 3600 + minutes * 60))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(
+        zone: Option<&str>,
+        server_zone: &str,
+        fraction_digits: Option<usize>,
+    ) -> TemporalFormat {
+        TemporalFormat {
+            date: DATE_FORMAT.to_string(),
+            time: TIME_FORMAT.to_string(),
+            timestamp: TIMESTAMP_FORMAT.to_string(),
+            fraction_digits,
+            zone: zone.map(|z| Zone::parse(z).unwrap()),
+            server_zone: Zone::parse(server_zone).unwrap(),
+        }
+    }
+
+    fn utc(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn parses_zones() {
+        assert!(matches!(Zone::parse("UTC"), Some(Zone::Utc)));
+        assert!(matches!(Zone::parse("local"), Some(Zone::Local)));
+        assert_eq!(
+            Zone::parse("+05:30")
+                .unwrap()
+                .offset_at(utc("2024-01-01 00:00:00")),
+            19800
+        );
+        assert_eq!(
+            Zone::parse("-0800")
+                .unwrap()
+                .offset_at(utc("2024-01-01 00:00:00")),
+            -28800
+        );
+        assert_eq!(
+            Zone::parse("+3")
+                .unwrap()
+                .offset_at(utc("2024-01-01 00:00:00")),
+            10800
+        );
+        assert!(Zone::parse("+15:00").is_none());
+        assert!(Zone::parse("Mars/Olympus").is_none());
+    }
+
+    #[test]
+    fn follows_dst_transitions() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        // 2024-03-31 02:00 CET and 2024-10-27 03:00 CEST, both 01:00 UTC
+        assert_eq!(berlin.offset_at(utc("2024-03-31 00:59:59")), 3600);
+        assert_eq!(berlin.offset_at(utc("2024-03-31 01:00:00")), 7200);
+        assert_eq!(berlin.offset_at(utc("2024-10-27 00:59:59")), 7200);
+        assert_eq!(berlin.offset_at(utc("2024-10-27 01:00:00")), 3600);
+
+        // daylight time spans the new year, ends 2024-04-07 03:00 AEDT
+        let sydney = Zone::parse("Australia/Sydney").unwrap();
+        assert_eq!(sydney.offset_at(utc("2024-01-15 00:00:00")), 39600);
+        assert_eq!(sydney.offset_at(utc("2024-04-06 15:59:59")), 39600);
+        assert_eq!(sydney.offset_at(utc("2024-04-06 16:00:00")), 36000);
+        assert_eq!(sydney.offset_at(utc("2024-07-15 00:00:00")), 36000);
+    }
+
+    #[test]
+    fn parses_timestamps_in_server_zone() {
+        let berlin = Zone::parse("Europe/Berlin").unwrap();
+        assert_eq!(
+            parse_timestamp("2024-01-15 12:00:00", &berlin),
+            Some(utc("2024-01-15 11:00:00"))
+        );
+        assert_eq!(
+            parse_timestamp("2024-07-15T12:00:00", &berlin),
+            Some(utc("2024-07-15 10:00:00"))
+        );
+        // the first hour of daylight time
+        assert_eq!(
+            parse_timestamp("2024-03-31 03:30:00", &berlin),
+            Some(utc("2024-03-31 01:30:00"))
+        );
+        // the offset of the value wins
+        assert_eq!(
+            parse_timestamp("2024-07-15 12:00:00 -05:00", &berlin),
+            Some(utc("2024-07-15 17:00:00"))
+        );
+        assert_eq!(parse_timestamp("2024-07-15", &berlin), None);
+    }
+
+    #[test]
+    fn formats_values() {
+        let format = format(Some("Europe/Berlin"), "utc", Some(3));
+        assert_eq!(
+            format.format_timestamp("2024-01-15 12:00:00").as_deref(),
+            Some("2024-01-15 13:00:00.000")
+        );
+        assert_eq!(
+            format
+                .format_timestamp("2024-07-15 12:00:00.123456")
+                .as_deref(),
+            Some("2024-07-15 14:00:00.123")
+        );
+        assert_eq!(
+            format.format_time(" 08:30:15.5 ").as_deref(),
+            Some("08:30:15.500")
+        );
+        assert_eq!(
+            format.format_date("2024-02-29").as_deref(),
+            Some("2024-02-29")
+        );
+        assert_eq!(format.format_date("2023-02-29"), None);
+        assert_eq!(format.format_time("25:00:00"), None);
+    }
+
+    #[test]
+    fn keeps_offset_without_zone() {
+        let format = format(None, "Europe/Berlin", Some(0));
+        assert_eq!(
+            format
+                .format_timestamp("2024-07-15 12:00:00.5 -05:00")
+                .as_deref(),
+            Some("2024-07-15 12:00:00")
+        );
+        assert_eq!(
+            format.format_timestamp("2024-07-15 12:00:00").as_deref(),
+            Some("2024-07-15 12:00:00")
+        );
+    }
+
+    #[test]
+    fn applies_to_temporal_columns() {
+        let mut format = format(Some("+01:00"), "utc", None);
+        format.date = "%d.%m.%Y".to_string();
+        let columns = vec![
+            ColDesc::new(0, "d".to_string(), "Date".to_string()),
+            ColDesc::new(1, "ts".to_string(), "Timestamp".to_string()),
+            ColDesc::new(2, "s".to_string(), "Varchar".to_string()),
+        ];
+        let mut rows = vec![
+            vec![
+                Some("2024-05-01".into()),
+                Some("2024-05-01 23:30:00".into()),
+                Some("2024-05-01".into()),
+            ],
+            vec![None, Some("not a timestamp".into()), None],
+        ];
+        format.apply(&columns, &mut rows);
+        assert_eq!(
+            rows[0],
+            vec![
+                Some("01.05.2024".into()),
+                Some("2024-05-02 00:30:00".into()),
+                Some("2024-05-01".into()),
+            ]
+        );
+        assert_eq!(rows[1], vec![None, Some("not a timestamp".into()), None]);
+    }
+}
@@ -0,0 +1,275 @@
+//! Client-side sort and filter of the fetched rows.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use crate::common::eng::{ColDesc, Row};
+use crate::temporal;
+
+#[derive(Debug, Clone, Copy)]
+pub enum FilterOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Case-insensitive substring.
+    Contains,
+    Null,
+    NotNull,
+}
+
+impl FilterOp {
+    pub fn parse(op: &str) -> Option<Self> {
+        match op.to_lowercase().as_str() {
+            "=" | "==" | "eq" => Some(FilterOp::Eq),
+            "!=" | "<>" | "ne" => Some(FilterOp::Ne),
+            "<" | "lt" => Some(FilterOp::Lt),
+            "<=" | "le" => Some(FilterOp::Le),
+            ">" | "gt" => Some(FilterOp::Gt),
+            ">=" | "ge" => Some(FilterOp::Ge),
+            "contains" | "like" => Some(FilterOp::Contains),
+            "null" => Some(FilterOp::Null),
+            "notnull" => Some(FilterOp::NotNull),
+            _ => None,
+        }
+    }
+
+    /// `null` and `notnull` take no value.
+    pub fn has_value(self) -> bool {
+        !matches!(self, FilterOp::Null | FilterOp::NotNull)
+    }
+}
+
+impl fmt::Display for FilterOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            FilterOp::Eq => "=",
+            FilterOp::Ne => "!=",
+            FilterOp::Lt => "<",
+            FilterOp::Le => "<=",
+            FilterOp::Gt => ">",
+            FilterOp::Ge => ">=",
+            FilterOp::Contains => "contains",
+            FilterOp::Null => "is null",
+            FilterOp::NotNull => "is not null",
+        };
+        f.write_str(op)
+    }
+}
+
+#[derive(Debug)]
+pub struct Filter {
+    /// 0-based column.
+    pub col: usize,
+    pub op: FilterOp,
+    pub value: String,
+}
+
+/// Sort and filters applied to the fetched rows when they are printed, the rows are kept.
+#[derive(Debug, Default)]
+pub struct View {
+    /// 0-based column and whether the order is descending.
+    sort: Option<(usize, bool)>,
+    /// All of them must match.
+    filters: Vec<Filter>,
+}
+
+impl View {
+    pub fn is_empty(&self) -> bool {
+        self.sort.is_none() && self.filters.is_empty()
+    }
+
+    pub fn sort(&mut self, col: usize, descending: bool) {
+        self.sort = Some((col, descending));
+    }
+
+    pub fn filter(&mut self, filter: Filter) {
+        self.filters.push(filter);
+    }
+
+    /// Rows which pass the filters in the sort order.
+    pub fn apply<'a>(&self, columns: &[ColDesc], rows: &'a [Row]) -> Vec<&'a Row> {
+        let mut res: Vec<&Row> = rows
+            .iter()
+            .filter(|row| {
+                self.filters
+                    .iter()
+                    .all(|f| matches(&columns[f.col], row[f.col].as_deref(), f))
+            })
+            .collect();
+        if let Some((col, descending)) = self.sort {
+            // stable, rows with equal values keep the fetch order
+            res.sort_by(|a, b| {
+                let (a, b) = (a[col].as_deref(), b[col].as_deref());
+                let order = compare(&columns[col], a, b);
+                // NULL is the last in both orders
+                if descending && a.is_some() && b.is_some() {
+                    order.reverse()
+                } else {
+                    order
+                }
+            });
+        }
+        res
+    }
+
+    /// `filter: a > 1, b contains x; sort: c desc`
+    pub fn describe(&self, columns: &[ColDesc]) -> String {
+        let mut parts = Vec::new();
+        if !self.filters.is_empty() {
+            let filters: Vec<String> = self
+                .filters
+                .iter()
+                .map(|f| match f.op.has_value() {
+                    true => format!("{} {} {}", columns[f.col].col_name, f.op, f.value),
+                    false => format!("{} {}", columns[f.col].col_name, f.op),
+                })
+                .collect();
+            parts.push(format!("filter: {}", filters.join(", ")));
+        }
+        if let Some((col, descending)) = self.sort {
+            parts.push(format!(
+                "sort: {} {}",
+                columns[col].col_name,
+                if descending { "desc" } else { "asc" }
+            ));
+        }
+        parts.join("; ")
+    }
+}
+
+fn matches(column: &ColDesc, value: Option<&str>, filter: &Filter) -> bool {
+    let Some(value) = value else {
+        return matches!(filter.op, FilterOp::Null);
+    };
+    let order = || compare(column, Some(value), Some(&filter.value));
+    match filter.op {
+        FilterOp::Null => false,
+        FilterOp::NotNull => true,
+        FilterOp::Contains => value.to_lowercase().contains(&filter.value.to_lowercase()),
+        FilterOp::Eq => order() == Ordering::Equal,
+        FilterOp::Ne => order() != Ordering::Equal,
+        FilterOp::Lt => order() == Ordering::Less,
+        FilterOp::Le => order() != Ordering::Greater,
+        FilterOp::Gt => order() == Ordering::Greater,
+        FilterOp::Ge => order() != Ordering::Less,
+    }
+}
+
+/// Numbers of numeric columns and points in time of temporal columns by value, other values
+/// as text, NULL is the last. Values are compared as fetched, before the display formats.
+pub fn compare(column: &ColDesc, a: Option<&str>, b: Option<&str>) -> Ordering {
+    match (a, b) {
+        (None, None) => Ordering::Equal,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(_), None) => Ordering::Less,
+        (Some(a), Some(b)) => {
+            if column.is_numeric() {
+                if let (Ok(a), Ok(b)) = (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+                    return a.total_cmp(&b);
+                }
+            }
+            let col_type = column.col_type.as_str();
+            match (
+                temporal::instant(col_type, a),
+                temporal::instant(col_type, b),
+            ) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column(col_type: &str) -> Vec<ColDesc> {
+        vec![ColDesc::new(0, "c".to_string(), col_type.to_string())]
+    }
+
+    fn sorted(col_type: &str, values: &[Option<&str>], descending: bool) -> Vec<Option<String>> {
+        let rows: Vec<Row> = values.iter().map(|v| vec![v.map(Into::into)]).collect();
+        let mut view = View::default();
+        view.sort(0, descending);
+        view.apply(&column(col_type), &rows)
+            .into_iter()
+            .map(|row| row[0].as_ref().map(|v| v.text.clone()))
+            .collect()
+    }
+
+    fn texts(values: &[Option<&str>]) -> Vec<Option<String>> {
+        values.iter().map(|v| v.map(str::to_string)).collect()
+    }
+
+    #[test]
+    fn compares_by_type() {
+        let numbers = [Some("10"), Some("9"), Some("-1.5")];
+        assert_eq!(
+            sorted("Double", &numbers, false),
+            texts(&[Some("-1.5"), Some("9"), Some("10")])
+        );
+        assert_eq!(
+            sorted("Varchar", &numbers, false),
+            texts(&[Some("-1.5"), Some("10"), Some("9")])
+        );
+
+        // the offset is taken into account, a shorter fraction is not less
+        let timestamps = [
+            Some("2024-05-01 10:00:00.5"),
+            Some("2024-05-01 12:00:00 +03:00"),
+            Some("2024-05-01 10:00:00"),
+            Some("2024-04-30"),
+        ];
+        assert_eq!(
+            sorted("Timestamp", &timestamps, false),
+            texts(&[
+                Some("2024-04-30"),
+                Some("2024-05-01 12:00:00 +03:00"),
+                Some("2024-05-01 10:00:00"),
+                Some("2024-05-01 10:00:00.5"),
+            ])
+        );
+        let times = [Some("9:30:00"), Some("10:00:00"), Some("09:00:00.25")];
+        assert_eq!(
+            sorted("Time", &times, false),
+            texts(&[Some("09:00:00.25"), Some("9:30:00"), Some("10:00:00")])
+        );
+
+        let filter = Filter {
+            col: 0,
+            op: FilterOp::Gt,
+            value: "2024-05-01 11:00:00".to_string(),
+        };
+        assert!(!matches(&column("Timestamp")[0], timestamps[0], &filter));
+        assert!(matches(
+            &column("Timestamp")[0],
+            Some("2024-05-01 09:00:00 -03:00"),
+            &filter
+        ));
+    }
+
+    #[test]
+    fn sorts_null_last() {
+        let values = [None, Some("2"), None, Some("1")];
+        assert_eq!(
+            sorted("Integer", &values, false),
+            texts(&[Some("1"), Some("2"), None, None])
+        );
+        assert_eq!(
+            sorted("Integer", &values, true),
+            texts(&[Some("2"), Some("1"), None, None])
+        );
+
+        let null = Filter {
+            col: 0,
+            op: FilterOp::Null,
+            value: String::new(),
+        };
+        assert!(matches(&column("Integer")[0], None, &null));
+        assert!(!matches(&column("Integer")[0], Some(""), &null));
+    }
+}